@@ -1,24 +1,36 @@
 use super::*;
+use frame_system::pallet_prelude::BlockNumberFor;
+use frame_support::sp_runtime::traits::Hash;
+use sp_std::collections::btree_map::BTreeMap;
+
+/// The most times `do_settle_all` clears the pairs looking for the proceeds of
+/// the sell legs of asset to asset intents.
+pub const SETTLE_ALL_ITERATIONS: u32 = 8;
+
+/// The most times `clear_intents` sizes the input of the exact output intents
+/// of a batch.
+pub const EXACT_OUTPUT_SIZINGS: u32 = 8;
+
+/// The subject the randomness shuffling a batch is drawn for.
+pub const SETTLEMENT_SUBJECT: &[u8] = b"anti-mev/settlement";
+
+/// The context signed along with a swap intent, see `signed_intent_payload`.
+pub const SIGNED_INTENT_CONTEXT: &[u8] = b"anti-mev/intent";
 
 impl<T: Config> Pallet<T> {
+    /// Record the output of a settled intent for its owner to claim, with the
+    /// input it didn't trade.
+    /// Returns the paid output.
     pub (crate) fn do_anti_mev_swap_currency_for_asset(
         asset_id: &AssetIdOf<T>,
-        recipient: &T::AccountId,
-        asset_out: &BalanceOf<T>,
-        total_cumulative_currency: &BalanceOf<T>,
-    ) -> DispatchResult {
-        let amount_in: BalanceOf<T> = Self::get_pair_currency_cumulative(&asset_id, &recipient);
-        let amount_out: AssetBalanceOf<T> = T::currency_to_asset(
-            asset_out.clone() * amount_in / total_cumulative_currency.clone()
-        );
-        let pallet_account = T::pallet_account();
-        T::Assets::transfer(
-            asset_id.clone(),
-            &pallet_account,
-            &recipient,
-            amount_out,
-            Preservation::Expendable,
-        )?;
+        intent_id: IntentId,
+        intent: &CurrencyToAssetIntentOf<T>,
+        amount_in: BalanceOf<T>,
+        amount_out: AssetBalanceOf<T>,
+    ) -> Result<AssetBalanceOf<T>, DispatchError> {
+        let recipient = &intent.owner;
+        let unused_input = intent.swap.locked_input().saturating_sub(amount_in);
+        Self::record_claim(asset_id, intent_id, recipient, unused_input, amount_out);
         Self::deposit_event(Event::SwappedCurrencyForAsset(
             asset_id.clone(),
             recipient.clone(),
@@ -26,109 +38,1800 @@ impl<T: Config> Pallet<T> {
             amount_in,
             amount_out,
         ));
-        Ok(())
+        Ok(amount_out)
     }
 
+    /// Record the output of a settled intent for its owner to claim, with the
+    /// input it didn't trade.
+    /// The output of a sell leg funds its buy leg instead, which is queued
+    /// unless it settles in the same pass.
+    /// Returns the paid output.
     pub (crate) fn do_anti_mev_swap_asset_for_currency(
         asset_id: &AssetIdOf<T>,
-        recipient: &T::AccountId,
+        intent_id: IntentId,
+        intent: &AssetToCurrencyIntentOf<T>,
+        amount_in: AssetBalanceOf<T>,
+        amount_out: BalanceOf<T>,
+        queue_next_leg: bool,
+    ) -> Result<BalanceOf<T>, DispatchError> {
+        let recipient = &intent.owner;
+        let unused_input = intent.swap.locked_input().saturating_sub(amount_in);
+        let currency_out = match <NextLegs<T>>::get(intent_id) {
+            // the currency stays in the pallet account and funds the buy leg
+            Some(_) if !queue_next_leg => Zero::zero(),
+            Some((bought_asset_id, min_output, deadline)) => {
+                if Self::queue_next_leg(bought_asset_id, recipient, amount_out, min_output, deadline) {
+                    Zero::zero()
+                } else {
+                    amount_out
+                }
+            }
+            None => amount_out,
+        };
+        Self::record_claim(asset_id, intent_id, recipient, currency_out, unused_input);
+        Self::deposit_event(Event::SwappedAssetForCurrency(
+            asset_id.clone(),
+            recipient.clone(),
+            recipient.clone(),
+            amount_out,
+            amount_in,
+        ));
+        Ok(amount_out)
+    }
+
+    /// The (input paid, output received) of a currency to asset intent trading
+    /// `amount_in` at the batch clearing price. An exact output intent gets its
+    /// output, its input was sized for it by `clear_intents`.
+    pub(crate) fn currency_for_asset_payout(
+        intent: &CurrencyToAssetIntentOf<T>,
+        amount_in: BalanceOf<T>,
+        asset_out: &BalanceOf<T>,
+        total_cumulative_currency: &BalanceOf<T>,
+    ) -> Result<(BalanceOf<T>, AssetBalanceOf<T>), ArithmeticError> {
+        Ok(match intent.swap {
+            AntiMevSwap::BasedInput { .. } => (
+                amount_in,
+                T::currency_to_asset(Self::pro_rata(
+                    *asset_out,
+                    amount_in,
+                    *total_cumulative_currency,
+                )?),
+            ),
+            AntiMevSwap::BasedOutput { output_amount, .. } => (amount_in, output_amount),
+        })
+    }
+
+    /// The (input paid, output received) of an asset to currency intent trading
+    /// `amount_in` at the batch clearing price. An exact output intent gets its
+    /// output, its input was sized for it by `clear_intents`.
+    pub(crate) fn asset_for_currency_payout(
+        intent: &AssetToCurrencyIntentOf<T>,
+        amount_in: AssetBalanceOf<T>,
         asset_out: &AssetBalanceOf<T>,
         total_cumulative_asset: &AssetBalanceOf<T>,
+    ) -> Result<(AssetBalanceOf<T>, BalanceOf<T>), ArithmeticError> {
+        Ok(match intent.swap {
+            AntiMevSwap::BasedInput { .. } => (
+                amount_in,
+                T::asset_to_currency(Self::pro_rata(
+                    *asset_out,
+                    amount_in,
+                    *total_cumulative_asset,
+                )?),
+            ),
+            AntiMevSwap::BasedOutput { output_amount, .. } => (amount_in, output_amount),
+        })
+    }
+
+    /// The input an exact output intent pays at the batch clearing price,
+    /// rounded up in favour of the pool.
+    pub(crate) fn clearing_input(
+        output_amount: BalanceOf<T>,
+        total_input: BalanceOf<T>,
+        total_output: BalanceOf<T>,
+    ) -> Result<BalanceOf<T>, ArithmeticError> {
+        if total_output.is_zero() {
+            return Ok(total_input);
+        }
+        Ok(output_amount
+            .checked_mul(&total_input)
+            .ok_or(ArithmeticError::Overflow)?
+            .checked_add(&(total_output - One::one()))
+            .ok_or(ArithmeticError::Overflow)?
+            / total_output)
+    }
+
+    /// The share `numerator / denominator` of `amount`, rounded down.
+    pub(crate) fn pro_rata<B: CheckedMul + CheckedDiv>(
+        amount: B,
+        numerator: B,
+        denominator: B,
+    ) -> Result<B, ArithmeticError> {
+        amount
+            .checked_mul(&numerator)
+            .ok_or(ArithmeticError::Overflow)?
+            .checked_div(&denominator)
+            .ok_or(ArithmeticError::DivisionByZero)
+    }
+
+    /// Keep the items `keep` holds for, stopping at the first error.
+    fn try_retain<I>(
+        items: &mut Vec<I>,
+        mut keep: impl FnMut(&I) -> Result<bool, ArithmeticError>,
+    ) -> Result<(), ArithmeticError> {
+        let mut kept = Vec::with_capacity(items.len());
+        for item in items.drain(..) {
+            if keep(&item)? {
+                kept.push(item);
+            }
+        }
+        *items = kept;
+        Ok(())
+    }
+
+    /// A queue is mature when both directions hold `MinQueueAmount` intents,
+    /// or when its first intent has waited `EpochLength` blocks.
+    /// Once the epoch has ended, the queue settles even if one side is empty.
+    pub(crate) fn is_queue_mature(
+        asset_id: &AssetIdOf<T>,
+        now: BlockNumberFor<T>,
+    ) -> Result<bool, Error<T>> {
+        ensure!(<Pairs<T>>::contains_key(asset_id.clone()), Error::<T>::PairNotFound);
+        let currency_len = Self::queue_len(asset_id, SwapDirection::CurrencyToAsset);
+        let asset_len = Self::queue_len(asset_id, SwapDirection::AssetToCurrency);
+        let min_queue_amount = T::MinQueueAmount::get();
+        if currency_len >= min_queue_amount && asset_len >= min_queue_amount {
+            return Ok(true);
+        }
+        Ok(<EpochStart<T>>::get(asset_id.clone())
+            .is_some_and(|start| now >= start.saturating_add(T::EpochLength::get())))
+    }
+
+    /// The weight of settling one pair. Every slot of both queues may be read
+    /// past cancelled intents, and at most `MinQueueAmount` live and
+    /// `MinQueueAmount` expired intents of each direction are settled.
+    pub(crate) fn settle_weight() -> Weight {
+        T::WeightInfo::settle_and_distribute(
+            T::MaxQueueLength::get().saturating_mul(2),
+            T::MinQueueAmount::get().saturating_mul(4),
+        )
+    }
+
+    /// The weight of `settle_all` settling `pair_count` pairs, after checking
+    /// the queues of `visited` pairs.
+    pub(crate) fn settle_all_weight(pair_count: u32, visited: u32) -> Weight {
+        Self::settle_weight()
+            .saturating_mul(pair_count as u64)
+            .saturating_add(T::DbWeight::get().reads(6).saturating_mul(visited as u64))
+    }
+
+    /// Settle the mature queues from the block hooks.
+    ///
+    /// Pairs are visited in storage order, starting after `SettlementCursor`.
+    /// When the remaining weight is not enough for another settlement the
+    /// cursor is stored, so the next block picks up where this one stopped.
+    pub(crate) fn settle_mature_queues(
+        now: BlockNumberFor<T>,
+        remaining_weight: Weight,
+    ) -> Weight {
+        let check_weight = T::DbWeight::get().reads(4);
+        let settle_weight = Self::settle_weight();
+        let mut consumed = T::DbWeight::get().reads_writes(1, 1);
+        if consumed.any_gt(remaining_weight) {
+            return Weight::zero();
+        }
+
+        let mut cursor = <SettlementCursor<T>>::get();
+        let mut pairs = match cursor.clone() {
+            Some(last) => <Pairs<T>>::iter_from(<Pairs<T>>::hashed_key_for(last)),
+            None => <Pairs<T>>::iter(),
+        }
+        .map(|(asset_id, _)| asset_id);
+        loop {
+            if consumed
+                .saturating_add(check_weight)
+                .saturating_add(settle_weight)
+                .any_gt(remaining_weight)
+            {
+                <SettlementCursor<T>>::set(cursor);
+                return consumed;
+            }
+            let Some(asset_id) = pairs.next() else {
+                // all pairs were visited, start over in the next block
+                <SettlementCursor<T>>::kill();
+                return consumed;
+            };
+
+            consumed.saturating_accrue(check_weight);
+            if Self::is_queue_mature(&asset_id, now).unwrap_or(false)
+                && !Self::is_bidding_open(&asset_id, now)
+            {
+                consumed.saturating_accrue(settle_weight);
+                if let Err(e) = Self::do_settle_pair(&asset_id) {
+                    log::warn!(
+                        target: LOG_TARGET,
+                        "Automatic settlement of {:?} failed: {:?}",
+                        asset_id,
+                        e
+                    );
+                }
+            }
+            cursor = Some(asset_id);
+        }
+    }
+
+    /// Settle up to `MinQueueAmount` queued intents of each direction against
+    /// the pair and distribute the outputs pro-rata.
+    ///
+    /// Opposing intents are matched against each other at the spot price, the
+    /// imbalance is filled against the curve. A batch may be one-sided, then
+    /// its intents are filled against the curve at a single uniform price.
+    ///
+    /// Intents past their deadline are refunded before the batch is cleared.
+    /// Intents whose minimum output can't be met at the batch price are
+    /// refunded, and the batch is cleared again over the remaining intents.
+    /// Intents after the batch stay queued for the next one.
+    ///
+    /// Returns the provider fee the batch paid.
+    #[transactional]
+    pub(crate) fn do_settle_and_distribute(
+        asset_id: &AssetIdOf<T>,
+    ) -> Result<BalanceOf<T>, DispatchError> {
+        let now = <frame_system::Pallet<T>>::block_number();
+        let batch = Self::take_pair_batch(asset_id, now)?;
+        let cleared = Self::clear_intents(&batch.pair, batch.buys.clone(), batch.sells.clone())?;
+        Self::distribute_batch(asset_id, batch.pair.clone(), &cleared, &[])?;
+        Self::close_pair_batch(&batch);
+        Ok(Self::batch_fee(&batch.pair, &cleared))
+    }
+
+    /// Settle every mature pair in one pass.
+    ///
+    /// The buy legs of asset to asset intents join the batch of their bought
+    /// pair in the same pass, funded with the proceeds of their sell legs. A
+    /// trader gets one coherent price on both pairs, and ring trades net
+    /// against each other in every pair before any reserves move.
+    ///
+    /// The proceeds of the sell legs depend on the clearing of every pair, they
+    /// are found by clearing all pairs again until they don't change, at most
+    /// `SETTLE_ALL_ITERATIONS` times. Without a fixed point the buy legs are
+    /// queued like in `do_settle_and_distribute`.
+    ///
+    /// Pairs left to the solvers are skipped, see `settles_without_solvers`.
+    /// The pairs are visited in storage order until `pair_count` mature ones
+    /// are found, the others wait for the next pass.
+    ///
+    /// Returns the (number of settled pairs, number of visited pairs,
+    /// provider fee their batches paid).
+    #[transactional]
+    pub(crate) fn do_settle_all(
+        pair_count: u32,
+    ) -> Result<(u32, u32, BalanceOf<T>), DispatchError> {
+        let now = <frame_system::Pallet<T>>::block_number();
+        let mut batches: Vec<PairBatch<T>> = Vec::new();
+        let mut visited = 0;
+        for asset_id in <Pairs<T>>::iter_keys() {
+            if batches.len() as u32 >= pair_count {
+                break;
+            }
+            visited += 1;
+            if Self::is_queue_mature(&asset_id, now)? && Self::settles_without_solvers(&asset_id, now) {
+                batches.push(Self::take_pair_batch(&asset_id, now)?);
+            }
+        }
+        ensure!(!batches.is_empty(), Error::<T>::QueueTooSmall);
+
+        // the buy legs, their input is only known once the sell legs are cleared
+        let mut legs: Vec<(IntentId, AssetIdOf<T>, CurrencyToAssetIntentOf<T>)> = Vec::new();
+        for batch in batches.iter() {
+            for (intent_id, intent) in batch.sells.iter() {
+                let Some((bought_asset_id, min_output, deadline)) = <NextLegs<T>>::get(intent_id)
+                else {
+                    continue;
+                };
+                legs.push((*intent_id, bought_asset_id, Intent {
+                    owner: intent.owner.clone(),
+                    swap: AntiMevSwap::BasedInput { input_amount: Zero::zero(), min_output },
+                    deadline,
+                    queued_at: intent.queued_at,
+                }));
+            }
+        }
+        // the bought pairs settle in the same pass, even without queued intents
+        legs.retain(|(_, bought_asset_id, _)| {
+            <Pairs<T>>::contains_key(bought_asset_id.clone())
+                && Self::settles_without_solvers(bought_asset_id, now)
+        });
+        for (_, bought_asset_id, _) in legs.iter() {
+            if !batches.iter().any(|batch| &batch.asset_id == bought_asset_id) {
+                batches.push(PairBatch {
+                    asset_id: bought_asset_id.clone(),
+                    pair: Self::get_pair(bought_asset_id)?,
+                    buys: Vec::new(),
+                    sells: Vec::new(),
+                    expired_buys: Vec::new(),
+                    expired_sells: Vec::new(),
+                });
+            }
+        }
+        ensure!(batches.len() as u32 <= pair_count, Error::<T>::TooManyPairs);
+
+        let mut leg_inputs: BTreeMap<IntentId, BalanceOf<T>> = BTreeMap::new();
+        let mut fixed_point = None;
+        for _ in 0..SETTLE_ALL_ITERATIONS {
+            let (cleared, proceeds) = Self::clear_pairs(&batches, &legs, &leg_inputs)?;
+            if proceeds == leg_inputs {
+                fixed_point = Some(cleared);
+                break;
+            }
+            leg_inputs = proceeds;
+        }
+        let (cleared, settled_legs) = match fixed_point {
+            Some(cleared) => (cleared, legs.iter().map(|(intent_id, _, _)| *intent_id).collect()),
+            None => {
+                log::warn!(
+                    target: LOG_TARGET,
+                    "No fixed point for {:?} buy legs, they are queued",
+                    legs.len()
+                );
+                (Self::clear_pairs(&batches, &[], &BTreeMap::new())?.0, Vec::new())
+            }
+        };
+
+        let mut fee: BalanceOf<T> = Zero::zero();
+        for (batch, cleared) in batches.iter().zip(cleared.iter()) {
+            Self::distribute_batch(&batch.asset_id, batch.pair.clone(), cleared, &settled_legs)?;
+            fee.saturating_accrue(Self::batch_fee(&batch.pair, cleared));
+        }
+        for batch in batches.iter() {
+            Self::close_pair_batch(batch);
+        }
+        Ok((batches.len() as u32, visited, fee))
+    }
+
+    /// Clear the batch of every pair, with the buy legs funded by `leg_inputs`.
+    /// Returns the (cleared batches, proceeds of the sell legs).
+    fn clear_pairs(
+        batches: &[PairBatch<T>],
+        legs: &[(IntentId, AssetIdOf<T>, CurrencyToAssetIntentOf<T>)],
+        leg_inputs: &BTreeMap<IntentId, BalanceOf<T>>,
+    ) -> Result<(Vec<ClearedBatch<T>>, BTreeMap<IntentId, BalanceOf<T>>), DispatchError> {
+        let mut cleared_batches = Vec::new();
+        let mut proceeds = BTreeMap::new();
+        for batch in batches {
+            let mut buys = batch.buys.clone();
+            for (intent_id, bought_asset_id, intent) in legs.iter() {
+                let input_amount = leg_inputs.get(intent_id).copied().unwrap_or_else(Zero::zero);
+                if bought_asset_id != &batch.asset_id || input_amount.is_zero() {
+                    continue;
+                }
+                let mut intent = intent.clone();
+                intent.swap = AntiMevSwap::BasedInput {
+                    input_amount,
+                    min_output: intent.swap.min_output(),
+                };
+                buys.push((*intent_id, intent));
+            }
+            let cleared = Self::clear_intents(&batch.pair, buys, batch.sells.clone())?;
+
+            // the same share `do_anti_mev_swap_asset_for_currency` pays
+            let (currency_out, _) = cleared.outputs(&batch.pair)?;
+            for (intent_id, intent) in cleared.sells.iter() {
+                if legs.iter().any(|(leg_id, _, _)| leg_id == intent_id) {
+                    let (_, amount_out) = Self::asset_for_currency_payout(
+                        intent,
+                        cleared.sell_input(intent_id, intent),
+                        &T::currency_to_asset(currency_out),
+                        &T::currency_to_asset(cleared.total_cumulative_asset),
+                    )?;
+                    proceeds.insert(*intent_id, amount_out);
+                }
+            }
+            cleared_batches.push(cleared);
+        }
+        Ok((cleared_batches, proceeds))
+    }
+
+    /// Take the next batch of a pair from its queues and refund the intents
+    /// past their deadline.
+    pub(crate) fn take_pair_batch(
+        asset_id: &AssetIdOf<T>,
+        now: BlockNumberFor<T>,
+    ) -> Result<PairBatch<T>, DispatchError> {
+        let pair = Self::get_pair(asset_id)?;
+        // a batch takes at most `MinQueueAmount` live intents of each direction
+        let (buys, expired_buys) = Self::batch_currency_intents(asset_id, now);
+        let (sells, expired_sells) = Self::batch_asset_intents(asset_id, now);
+        ensure!(
+            !buys.is_empty()
+                || !sells.is_empty()
+                || !expired_buys.is_empty()
+                || !expired_sells.is_empty(),
+            Error::<T>::QueueTooSmall
+        );
+        for (intent_id, intent) in expired_buys.iter() {
+            Self::do_anti_mev_refund_currency(
+                asset_id,
+                *intent_id,
+                intent,
+                Event::ExpiredSwapCurrencyForAsset,
+            )?;
+        }
+        for (intent_id, intent) in expired_sells.iter() {
+            Self::do_anti_mev_refund_asset(
+                asset_id,
+                *intent_id,
+                intent,
+                Event::ExpiredSwapAssetForCurrency,
+            )?;
+        }
+        Ok(PairBatch {
+            asset_id: asset_id.clone(),
+            pair,
+            buys,
+            sells,
+            expired_buys,
+            expired_sells,
+        })
+    }
+
+    /// Clear the intents against the pair, dropping the intents whose limit
+    /// can't be met until the rest of the batch clears.
+    ///
+    /// An exact output intent trades the input its output needs at the batch
+    /// price, so a generous maximum input doesn't move the price against the
+    /// rest of the batch. It starts at its maximum input and is sized down to
+    /// the input needed at the resulting price, which improves with every
+    /// pass, at most `EXACT_OUTPUT_SIZINGS` times. An intent whose output
+    /// needs more than its input by then is refunded.
+    pub(crate) fn clear_intents(
+        pair: &PairOf<T>,
+        batch_buys: Vec<(IntentId, CurrencyToAssetIntentOf<T>)>,
+        batch_sells: Vec<(IntentId, AssetToCurrencyIntentOf<T>)>,
+    ) -> Result<ClearedBatch<T>, DispatchError> {
+        let mut buys = batch_buys.clone();
+        let mut sells = batch_sells.clone();
+        let mut buy_inputs: BTreeMap<IntentId, BalanceOf<T>> = BTreeMap::new();
+        let mut sell_inputs: BTreeMap<IntentId, AssetBalanceOf<T>> = BTreeMap::new();
+        let mut sizings = 0;
+
+        let (total_cumulative_currency, total_cumulative_asset, simulation) = loop {
+            // Sum of all currency and asset in the batch
+            let total_cumulative_currency: BalanceOf<T> =
+                Self::calculate_cumulative_currency(&buys, &buy_inputs)?;
+            let total_cumulative_asset: BalanceOf<T> =
+                T::asset_to_currency(Self::calculate_cumulative_asset(&sells, &sell_inputs)?);
+            let simulation = Self::simulate_settlement(
+                pair,
+                total_cumulative_currency,
+                total_cumulative_asset,
+            )?;
+            let (currency_out, asset_out) = Self::batch_outputs(
+                pair,
+                total_cumulative_currency,
+                total_cumulative_asset,
+                &simulation,
+            )?;
+
+            // Refund the intents whose limit can't be met, size the exact output ones
+            let resize = sizings < EXACT_OUTPUT_SIZINGS;
+            let mut resized = false;
+            let buys_len = buys.len();
+            Self::try_retain(&mut buys, |(intent_id, intent)| match intent.swap {
+                AntiMevSwap::BasedInput { input_amount, min_output } => {
+                    let amount_out = T::currency_to_asset(Self::pro_rata(
+                        asset_out,
+                        input_amount,
+                        total_cumulative_currency,
+                    )?);
+                    Ok(amount_out >= min_output)
+                }
+                AntiMevSwap::BasedOutput { max_input, output_amount } => {
+                    let needed = Self::clearing_input(
+                        T::asset_to_currency(output_amount),
+                        total_cumulative_currency,
+                        asset_out,
+                    )?;
+                    let input = buy_inputs.entry(*intent_id).or_insert(max_input);
+                    if needed > max_input {
+                        return Ok(false);
+                    }
+                    if resize && needed != *input {
+                        *input = needed;
+                        resized = true;
+                    }
+                    Ok(needed <= *input)
+                }
+            })?;
+            let sells_len = sells.len();
+            Self::try_retain(&mut sells, |(intent_id, intent)| match intent.swap {
+                AntiMevSwap::BasedInput { input_amount, min_output } => {
+                    let amount_in = T::asset_to_currency(input_amount);
+                    let amount_out =
+                        Self::pro_rata(currency_out, amount_in, total_cumulative_asset)?;
+                    Ok(amount_out >= min_output)
+                }
+                AntiMevSwap::BasedOutput { max_input, output_amount } => {
+                    let needed = T::currency_to_asset(Self::clearing_input(
+                        output_amount,
+                        total_cumulative_asset,
+                        currency_out,
+                    )?);
+                    let input = sell_inputs.entry(*intent_id).or_insert(max_input);
+                    if needed > max_input {
+                        return Ok(false);
+                    }
+                    if resize && needed != *input {
+                        *input = needed;
+                        resized = true;
+                    }
+                    Ok(needed <= *input)
+                }
+            })?;
+            if resized {
+                sizings += 1;
+            }
+            if buys.len() == buys_len && sells.len() == sells_len && !resized {
+                break (total_cumulative_currency, total_cumulative_asset, simulation);
+            }
+            if buys.is_empty() && sells.is_empty() {
+                // every intent of the batch was refunded
+                break (
+                    Zero::zero(),
+                    Zero::zero(),
+                    Self::simulate_settlement(pair, Zero::zero(), Zero::zero())?,
+                );
+            }
+        };
+        buy_inputs.retain(|intent_id, _| buys.iter().any(|(id, _)| id == intent_id));
+        sell_inputs.retain(|intent_id, _| sells.iter().any(|(id, _)| id == intent_id));
+        Ok(ClearedBatch {
+            batch_buys,
+            batch_sells,
+            buys,
+            sells,
+            buy_inputs,
+            sell_inputs,
+            total_cumulative_currency,
+            total_cumulative_asset,
+            simulation,
+        })
+    }
+
+    /// Refund the intents dropped from the cleared batch, pay the outputs of
+    /// the others and store the reserves the pair settles on.
+    /// The proceeds of the sell legs in `settled_legs` already fund their buy
+    /// legs in the same settlement, the others are queued.
+    pub(crate) fn distribute_batch(
+        asset_id: &AssetIdOf<T>,
+        mut pair: PairOf<T>,
+        cleared: &ClearedBatch<T>,
+        settled_legs: &[IntentId],
     ) -> DispatchResult {
-        let amount_in: AssetBalanceOf<T> = Self::get_pair_asset_cumulative(&asset_id, &recipient);
-        let amount_out: BalanceOf<T> = T::asset_to_currency(
-            asset_out.clone() * amount_in / total_cumulative_asset.clone()
+        let ClearedBatch {
+            batch_buys,
+            batch_sells,
+            buys,
+            sells,
+            total_cumulative_currency,
+            total_cumulative_asset,
+            simulation,
+            ..
+        } = cleared;
+        let (total_cumulative_currency, total_cumulative_asset) =
+            (*total_cumulative_currency, *total_cumulative_asset);
+        let (temporary_currency_reserve, temporary_asset_reserve) =
+            (simulation.currency_reserve, simulation.asset_reserve);
+        let (currency_out, asset_out) = cleared.outputs(&pair)?;
+
+        // Refund
+        for (intent_id, intent) in batch_buys.iter().filter(|buy| !buys.contains(buy)) {
+            Self::do_anti_mev_refund_currency(
+                asset_id,
+                *intent_id,
+                intent,
+                Event::RefundedSwapCurrencyForAsset,
+            )?;
+        }
+        for (intent_id, intent) in batch_sells.iter().filter(|sell| !sells.contains(sell)) {
+            Self::do_anti_mev_refund_asset(
+                asset_id,
+                *intent_id,
+                intent,
+                Event::RefundedSwapAssetForCurrency,
+            )?;
+        }
+
+        // Distribute in a random order, the position of an intent in the queue
+        // doesn't decide which buy legs are queued first
+        let mut buys = buys.clone();
+        let mut sells = sells.clone();
+        Self::shuffle(&mut buys, Self::batch_seed(asset_id, SwapDirection::CurrencyToAsset).0);
+        Self::shuffle(&mut sells, Self::batch_seed(asset_id, SwapDirection::AssetToCurrency).0);
+
+        // Distribute, exact output intents get the input they didn't trade back
+        let mut paid_asset: AssetBalanceOf<T> = Zero::zero();
+        for (intent_id, intent) in buys.iter() {
+            let (amount_in, amount_out) = Self::currency_for_asset_payout(
+                intent,
+                cleared.buy_input(intent_id, intent),
+                &asset_out,
+                &total_cumulative_currency,
+            )?;
+            let paid = Self::do_anti_mev_swap_currency_for_asset(
+                asset_id,
+                *intent_id,
+                intent,
+                amount_in,
+                amount_out,
+            )?;
+            paid_asset.saturating_accrue(paid);
+        }
+
+        let mut paid_currency: BalanceOf<T> = Zero::zero();
+        for (intent_id, intent) in sells.iter() {
+            let (amount_in, amount_out) = Self::asset_for_currency_payout(
+                intent,
+                cleared.sell_input(intent_id, intent),
+                &T::currency_to_asset(currency_out),
+                &T::currency_to_asset(total_cumulative_asset),
+            )?;
+            let paid = Self::do_anti_mev_swap_asset_for_currency(
+                asset_id,
+                *intent_id,
+                intent,
+                amount_in,
+                amount_out,
+                !settled_legs.contains(intent_id),
+            )?;
+            paid_currency.saturating_accrue(paid);
+        }
+
+        // Update the reserves, the fee and the rounding dust stay with the LPs
+        pair.currency_reserve = pair
+            .currency_reserve
+            .checked_add(&total_cumulative_currency)
+            .ok_or(ArithmeticError::Overflow)?
+            .saturating_sub(paid_currency);
+        pair.token_reserve = pair
+            .token_reserve
+            .checked_add(&T::currency_to_asset(total_cumulative_asset))
+            .ok_or(ArithmeticError::Overflow)?
+            .saturating_sub(paid_asset);
+        let pallet_account = T::pallet_account();
+        ensure!(
+            <T as Config>::Currency::free_balance(&pallet_account) >= temporary_currency_reserve,
+            Error::<T>::CurrencyLeak
+        );
+        ensure!(
+            T::Assets::balance(asset_id.clone(), &pallet_account)
+                >= T::currency_to_asset(temporary_asset_reserve),
+            Error::<T>::AssetLeak
         );
+        <Pairs<T>>::insert(asset_id.clone(), pair);
+
+        // emit SettlementPerformed(BaseCurrencyOut, QuoteCurrencyOut, block.timestamp);
+        Self::deposit_event(Event::DistributeSettlement(
+            asset_id.clone(),
+            currency_out,
+            T::currency_to_asset(asset_out),
+            simulation.netted_currency,
+            T::currency_to_asset(simulation.netted_asset),
+            simulation.routed_currency,
+            T::currency_to_asset(simulation.routed_asset),
+        ));
+        Ok(())
+    }
+
+    /// The seed shuffling one direction of the batch of a pair.
+    /// Returns the (seed, block the seed is known since). Only the intents
+    /// queued before that block are settled with it, see `batch_currency_intents`.
+    pub(crate) fn batch_seed(
+        asset_id: &AssetIdOf<T>,
+        direction: SwapDirection,
+    ) -> (T::Hash, BlockNumberFor<T>) {
+        let subject = (SETTLEMENT_SUBJECT, asset_id, direction).encode();
+        T::Randomness::random(&subject)
+    }
+
+    /// Shuffle the items with Fisher-Yates, drawing each swap from the seed.
+    pub(crate) fn shuffle<I>(items: &mut [I], seed: T::Hash) {
+        for i in (1..items.len()).rev() {
+            let draw = T::Hashing::hash_of(&(seed, i as u32));
+            let j = u32::decode(&mut draw.as_ref()).unwrap_or_default() as usize % (i + 1);
+            items.swap(i, j);
+        }
+    }
+
+    /// Remove the settled intents of the batch from the queues of its pair.
+    pub(crate) fn close_pair_batch(batch: &PairBatch<T>) {
+        let asset_id = &batch.asset_id;
+        // Reset for the next settlement period, the rest of the queue carries over
+        let settled_buys = batch.buys.len() + batch.expired_buys.len();
+        let settled_sells = batch.sells.len() + batch.expired_sells.len();
+        Self::pop_from_queue(asset_id, SwapDirection::CurrencyToAsset, settled_buys as u32);
+        Self::pop_from_queue(asset_id, SwapDirection::AssetToCurrency, settled_sells as u32);
+        let mut pending = <PendingInputs<T>>::get(asset_id.clone());
+        for (intent_id, intent) in batch.buys.iter().chain(batch.expired_buys.iter()) {
+            <CurrencyToAssetIntents<T>>::remove(intent_id);
+            pending.0.saturating_reduce(intent.swap.locked_input());
+        }
+        for (intent_id, intent) in batch.sells.iter().chain(batch.expired_sells.iter()) {
+            <AssetToCurrencyIntents<T>>::remove(intent_id);
+            <NextLegs<T>>::remove(intent_id);
+            pending.1.saturating_reduce(intent.swap.locked_input());
+        }
+        <PendingInputs<T>>::insert(asset_id.clone(), pending);
+        // the bidding on the batch is over
+        <BiddingEnd<T>>::remove(asset_id.clone());
+        <BestBids<T>>::remove(asset_id.clone());
+        <BiddingIntents<T>>::remove(asset_id.clone());
+        // the epoch ends with its last intent
+        if !Self::has_pending_intents(asset_id) {
+            <EpochStart<T>>::remove(asset_id.clone());
+        }
+    }
+
+    /// Queue the buy leg of an asset to asset swap with the currency its sell
+    /// leg produced. The currency is left to claim when the buy leg can't be
+    /// queued, so settling the pairs in any order is safe.
+    /// Returns whether the buy leg was queued.
+    pub(crate) fn queue_next_leg(
+        bought_asset_id: AssetIdOf<T>,
+        recipient: &T::AccountId,
+        amount_in: BalanceOf<T>,
+        min_output: AssetBalanceOf<T>,
+        deadline: BlockNumberFor<T>,
+    ) -> bool {
+        if amount_in.is_zero() {
+            return true;
+        }
+        let queued = <Pairs<T>>::contains_key(bought_asset_id.clone())
+            && Self::add_currency_to_asset_tx(
+                bought_asset_id.clone(),
+                AntiMevSwap::BasedInput { input_amount: amount_in, min_output },
+                deadline,
+                recipient.clone(),
+            )
+            .is_ok();
+        if !queued {
+            Self::deposit_event(Event::RefundedNextLeg(
+                bought_asset_id,
+                recipient.clone(),
+                amount_in,
+            ));
+        }
+        queued
+    }
+
+    /// Check the signature and the nonce of a signed intent, lock the input of
+    /// its signer and queue it.
+    pub(crate) fn do_submit_signed_intent(
+        intent: SignedIntentOf<T>,
+        signature: &T::OffchainSignature,
+    ) -> DispatchResult {
+        ensure!(
+            signature.verify(&Self::signed_intent_payload(&intent)[..], &intent.signer),
+            Error::<T>::BadSignature
+        );
+        <SignedIntentNonces<T>>::try_mutate(&intent.signer, |nonce| {
+            ensure!(*nonce == intent.nonce, Error::<T>::BadNonce);
+            *nonce = nonce.saturating_add(1);
+            Ok::<_, Error<T>>(())
+        })?;
+        Self::check_deadline(&intent.deadline)?;
+        match intent.direction {
+            SwapDirection::CurrencyToAsset => Self::queue_swap_currency_for_asset(
+                intent.signer,
+                intent.asset_id,
+                AntiMevSwap::BasedInput {
+                    input_amount: intent.amount_in,
+                    min_output: T::currency_to_asset(intent.min_output),
+                },
+                intent.deadline,
+            ),
+            SwapDirection::AssetToCurrency => Self::queue_swap_asset_for_currency(
+                intent.signer,
+                intent.asset_id,
+                AntiMevSwap::BasedInput {
+                    input_amount: T::currency_to_asset(intent.amount_in),
+                    min_output: intent.min_output,
+                },
+                intent.deadline,
+            ),
+        }
+    }
+
+    /// The payload signed off-chain for a swap intent: the intent with
+    /// `SIGNED_INTENT_CONTEXT` and the genesis hash, so its signature is valid
+    /// neither on another chain nor as another message of the signer.
+    pub fn signed_intent_payload(intent: &SignedIntentOf<T>) -> Vec<u8> {
+        let genesis_hash = <frame_system::Pallet<T>>::block_hash(BlockNumberFor::<T>::zero());
+        (SIGNED_INTENT_CONTEXT, genesis_hash, intent).encode()
+    }
+
+    /// Add a settlement payout to the claim of an intent.
+    pub(crate) fn record_claim(
+        asset_id: &AssetIdOf<T>,
+        intent_id: IntentId,
+        owner: &T::AccountId,
+        currency: BalanceOf<T>,
+        asset: AssetBalanceOf<T>,
+    ) {
+        if currency.is_zero() && asset.is_zero() {
+            return;
+        }
+        <Claims<T>>::mutate(asset_id.clone(), intent_id, |claim| {
+            let claim = claim.get_or_insert_with(|| Claim {
+                owner: owner.clone(),
+                currency: Zero::zero(),
+                asset: Zero::zero(),
+            });
+            claim.currency.saturating_accrue(currency);
+            claim.asset.saturating_accrue(asset);
+        });
+    }
+
+    /// Pay out the claim of an intent to its owner.
+    /// A failing transfer only holds back this claim, never a settlement.
+    pub(crate) fn do_claim(asset_id: &AssetIdOf<T>, intent_id: IntentId) -> DispatchResult {
+        let Claim { owner, currency, asset } = <Claims<T>>::take(asset_id.clone(), intent_id)
+            .ok_or(Error::<T>::ClaimNotFound)?;
         let pallet_account = T::pallet_account();
-        if recipient.clone() != pallet_account {
+        if !currency.is_zero() {
             <T as pallet::Config>::Currency::transfer(
                 &pallet_account,
-                &recipient,
-                amount_out,
+                &owner,
+                currency,
                 ExistenceRequirement::AllowDeath,
             )?;
         }
-        Self::deposit_event(Event::SwappedAssetForCurrency(
+        if !asset.is_zero() {
+            T::Assets::transfer(
+                asset_id.clone(),
+                &pallet_account,
+                &owner,
+                asset,
+                Preservation::Expendable,
+            )?;
+        }
+        Self::deposit_event(Event::Claimed(asset_id.clone(), intent_id, owner, currency, asset));
+        Ok(())
+    }
+
+    /// Pay `ExecutorReward` from the reward pot to the executor of a settlement,
+    /// but no more than the provider fee the settlement collected: settling
+    /// dust batches earns nothing.
+    pub(crate) fn reward_executor(executor: &T::AccountId, fee: BalanceOf<T>) {
+        Self::pay_from_reward_pot(executor, T::ExecutorReward::get().min(fee), Event::ExecutorRewarded);
+    }
+
+    /// The provider fee a cleared batch pays on the volume it routes through
+    /// the curve, the volume netted at the spot price pays none.
+    pub(crate) fn batch_fee(pair: &PairOf<T>, cleared: &ClearedBatch<T>) -> BalanceOf<T> {
+        Self::provider_fee(
+            pair,
+            cleared.simulation.routed_currency,
+            T::currency_to_asset(cleared.simulation.routed_asset),
+        )
+    }
+
+    /// The provider fee on inputs traded against the pair, valued in currency
+    /// at the spot price.
+    pub(crate) fn provider_fee(
+        pair: &PairOf<T>,
+        currency_in: BalanceOf<T>,
+        asset_in: AssetBalanceOf<T>,
+    ) -> BalanceOf<T> {
+        let fee = |amount: BalanceOf<T>| {
+            amount.saturating_sub(
+                amount.saturating_mul(T::net_amount_numerator()) / T::ProviderFeeDenominator::get(),
+            )
+        };
+        let asset_fee = T::currency_to_asset(fee(T::asset_to_currency(asset_in)));
+        fee(currency_in).saturating_add(Self::currency_value(pair, asset_fee).unwrap_or_else(Zero::zero))
+    }
+
+    /// Pay `amount` from the reward pot, `event` tells what for.
+    /// An empty pot pays nothing, the settlement still goes through.
+    pub(crate) fn pay_from_reward_pot(
+        recipient: &T::AccountId,
+        amount: BalanceOf<T>,
+        event: fn(T::AccountId, BalanceOf<T>) -> Event<T>,
+    ) {
+        if amount.is_zero() {
+            return;
+        }
+        match <T as pallet::Config>::Currency::transfer(
+            &T::reward_pot(),
+            recipient,
+            amount,
+            ExistenceRequirement::KeepAlive,
+        ) {
+            Ok(()) => Self::deposit_event(event(recipient.clone(), amount)),
+            Err(e) => log::warn!(
+                target: LOG_TARGET,
+                "{:?} was not paid from the reward pot: {:?}",
+                recipient,
+                e
+            ),
+        }
+    }
+
+    /// Dry-run the next settlement of the pair without touching storage.
+    /// Intents whose limit can't be met, and intents past their deadline, are
+    /// refunded their whole input.
+    pub fn preview_settlement(
+        asset_id: &AssetIdOf<T>,
+    ) -> Result<SettlementPreviewOf<T>, DispatchError> {
+        let now = frame_system::Pallet::<T>::block_number();
+        let pair = Self::get_pair(asset_id)?;
+        let (buys, expired_buys) = Self::batch_currency_intents(asset_id, now);
+        let (sells, expired_sells) = Self::batch_asset_intents(asset_id, now);
+        let cleared = Self::clear_intents(&pair, buys, sells)?;
+        let (currency_out, asset_out) = cleared.outputs(&pair)?;
+        let (total_cumulative_currency, total_cumulative_asset) =
+            (cleared.total_cumulative_currency, cleared.total_cumulative_asset);
+
+        let mut payouts = Vec::new();
+        let mut currency_reserve = pair
+            .currency_reserve
+            .checked_add(&total_cumulative_currency)
+            .ok_or(ArithmeticError::Overflow)?;
+        let mut token_reserve = pair
+            .token_reserve
+            .checked_add(&T::currency_to_asset(total_cumulative_asset))
+            .ok_or(ArithmeticError::Overflow)?;
+        for (intent_id, intent) in cleared.batch_buys.iter().chain(expired_buys.iter()) {
+            let settled = cleared.buys.iter().any(|(id, _)| id == intent_id);
+            let (currency, asset) = if settled {
+                let (amount_in, amount_out) = Self::currency_for_asset_payout(
+                    intent,
+                    cleared.buy_input(intent_id, intent),
+                    &asset_out,
+                    &total_cumulative_currency,
+                )?;
+                let unused_input = intent.swap.locked_input().saturating_sub(amount_in);
+                token_reserve = token_reserve.saturating_sub(amount_out);
+                (unused_input, amount_out)
+            } else {
+                (intent.swap.locked_input(), Zero::zero())
+            };
+            payouts.push(IntentPayout {
+                intent_id: *intent_id,
+                owner: intent.owner.clone(),
+                direction: SwapDirection::CurrencyToAsset,
+                settled,
+                currency,
+                asset,
+            });
+        }
+        for (intent_id, intent) in cleared.batch_sells.iter().chain(expired_sells.iter()) {
+            let settled = cleared.sells.iter().any(|(id, _)| id == intent_id);
+            let (currency, asset) = if settled {
+                let (amount_in, amount_out) = Self::asset_for_currency_payout(
+                    intent,
+                    cleared.sell_input(intent_id, intent),
+                    &T::currency_to_asset(currency_out),
+                    &T::currency_to_asset(total_cumulative_asset),
+                )?;
+                let unused_input = intent.swap.locked_input().saturating_sub(amount_in);
+                currency_reserve = currency_reserve.saturating_sub(amount_out);
+                (amount_out, unused_input)
+            } else {
+                (Zero::zero(), intent.swap.locked_input())
+            };
+            payouts.push(IntentPayout {
+                intent_id: *intent_id,
+                owner: intent.owner.clone(),
+                direction: SwapDirection::AssetToCurrency,
+                settled,
+                currency,
+                asset,
+            });
+        }
+
+        let price = |currency: BalanceOf<T>, asset: BalanceOf<T>| {
+            if asset.is_zero() {
+                FixedU128::zero()
+            } else {
+                FixedU128::saturating_from_rational(
+                    T::currency_to_asset(currency),
+                    T::currency_to_asset(asset),
+                )
+            }
+        };
+        Ok(SettlementPreview {
+            buy_price: price(total_cumulative_currency, asset_out),
+            sell_price: price(currency_out, total_cumulative_asset),
+            currency_reserve,
+            token_reserve,
+            payouts,
+        })
+    }
+
+    /// The (currency paid to the sellers, asset paid to the buyers) of a batch
+    /// settling on `simulation`.
+    pub(crate) fn batch_outputs(
+        pair: &PairOf<T>,
+        total_cumulative_currency: BalanceOf<T>,
+        total_cumulative_asset: BalanceOf<T>,
+        simulation: &SettlementSimulation<BalanceOf<T>>,
+    ) -> Result<(BalanceOf<T>, BalanceOf<T>), ArithmeticError> {
+        let currency_out = pair
+            .currency_reserve
+            .checked_add(&total_cumulative_currency)
+            .ok_or(ArithmeticError::Overflow)?
+            .checked_sub(&simulation.currency_reserve)
+            .ok_or(ArithmeticError::Underflow)?;
+        let asset_out = T::asset_to_currency(pair.token_reserve)
+            .checked_add(&total_cumulative_asset)
+            .ok_or(ArithmeticError::Overflow)?
+            .checked_sub(&simulation.asset_reserve)
+            .ok_or(ArithmeticError::Underflow)?;
+        Ok((currency_out, asset_out))
+    }
+
+    /// Simulate the batch against the pair at a single uniform clearing price,
+    /// so neither direction of the batch trades first.
+    pub fn simulate_settlement(
+        pair: &PairOf<T>,
+        total_cumulative_currency: BalanceOf<T>,
+        total_cumulative_asset: BalanceOf<T>,
+    ) -> Result<SettlementSimulation<BalanceOf<T>>, Error<T>> {
+        Self::simulate_batch(
+            pair,
+            total_cumulative_currency,
+            total_cumulative_asset,
+            Self::clear_batch,
+        )
+    }
+
+    /// Reference implementation of `simulate_settlement`: push the batch through
+    /// the curve in `Fragment` steps in both orderings and take the mean.
+    /// Its cost grows with `Fragment`, it is only kept to check the closed form.
+    pub fn simulate_fragmented_settlement(
+        pair: &PairOf<T>,
+        total_cumulative_currency: BalanceOf<T>,
+        total_cumulative_asset: BalanceOf<T>,
+    ) -> Result<SettlementSimulation<BalanceOf<T>>, Error<T>> {
+        Self::simulate_batch(
+            pair,
+            total_cumulative_currency,
+            total_cumulative_asset,
+            |pair, modified_cumulative_currency, modified_cumulative_asset| {
+                let currency_first = Self::simulate_fragments(
+                    pair,
+                    modified_cumulative_currency,
+                    modified_cumulative_asset,
+                    SwapDirection::CurrencyToAsset,
+                );
+                let asset_first = Self::simulate_fragments(
+                    pair,
+                    modified_cumulative_currency,
+                    modified_cumulative_asset,
+                    SwapDirection::AssetToCurrency,
+                );
+                let two: BalanceOf<T> = 2u32.into();
+                Ok((
+                    (currency_first.0 + asset_first.0) / two,
+                    (currency_first.1 + asset_first.1) / two,
+                ))
+            },
+        )
+    }
+
+    /// Match the opposing flows of the batch at the spot price of the pair,
+    /// only the imbalance is routed through `clear`. Matched volume has no
+    /// price impact and pays no provider fee.
+    ///
+    /// The routed input net of the provider fee goes through `clear`, like in
+    /// `cp_get_output_amount`. The fee is added to the reserves for the LPs.
+    fn simulate_batch(
+        pair: &PairOf<T>,
+        total_cumulative_currency: BalanceOf<T>,
+        total_cumulative_asset: BalanceOf<T>,
+        clear: impl FnOnce(
+            &PairOf<T>,
+            BalanceOf<T>,
+            BalanceOf<T>,
+        ) -> Result<(BalanceOf<T>, BalanceOf<T>), Error<T>>,
+    ) -> Result<SettlementSimulation<BalanceOf<T>>, Error<T>> {
+        // value of the sold asset at the spot price
+        let asset_value = total_cumulative_asset
+            .checked_mul(&pair.currency_reserve)
+            .ok_or(Error::<T>::Overflow)?
+            / T::asset_to_currency(pair.token_reserve);
+        let (netted_currency, netted_asset) = if total_cumulative_currency >= asset_value {
+            (asset_value, total_cumulative_asset)
+        } else {
+            let currency_value = total_cumulative_currency
+                .checked_mul(&T::asset_to_currency(pair.token_reserve))
+                .ok_or(Error::<T>::Overflow)?
+                / pair.currency_reserve;
+            (total_cumulative_currency, currency_value)
+        };
+        let (netted_currency, netted_asset) = if netted_currency.is_zero() || netted_asset.is_zero() {
+            (Zero::zero(), Zero::zero())
+        } else {
+            (netted_currency, netted_asset)
+        };
+        let routed_currency = total_cumulative_currency - netted_currency;
+        let routed_asset = total_cumulative_asset - netted_asset;
+
+        let modified_cumulative_currency = routed_currency
+            .checked_mul(&T::net_amount_numerator())
+            .ok_or(Error::<T>::Overflow)?
+            / T::ProviderFeeDenominator::get();
+        let modified_cumulative_asset = routed_asset
+            .checked_mul(&T::net_amount_numerator())
+            .ok_or(Error::<T>::Overflow)?
+            / T::ProviderFeeDenominator::get();
+        let currency_fee = routed_currency - modified_cumulative_currency;
+        let asset_fee = routed_asset - modified_cumulative_asset;
+
+        let (temporary_currency_reserve, temporary_asset_reserve) =
+            clear(pair, modified_cumulative_currency, modified_cumulative_asset)?;
+        let temporary_currency_reserve =
+            temporary_currency_reserve.checked_add(&currency_fee).ok_or(Error::<T>::Overflow)?;
+        let temporary_asset_reserve =
+            temporary_asset_reserve.checked_add(&asset_fee).ok_or(Error::<T>::Overflow)?;
+
+        // Calculate the output
+        let currency_ceiling =
+            pair.currency_reserve.checked_add(&routed_currency).ok_or(Error::<T>::Overflow)?;
+        let asset_ceiling = T::asset_to_currency(pair.token_reserve)
+            .checked_add(&routed_asset)
+            .ok_or(Error::<T>::Overflow)?;
+        if temporary_currency_reserve > currency_ceiling {
+            log::error!(
+                target: LOG_TARGET,
+                "Currency overflow: {:?} > {:?} + {:?}",
+                temporary_currency_reserve,
+                pair.currency_reserve,
+                routed_currency
+            );
+            return Err(Error::<T>::CurrencyOverflow);
+        }
+        if temporary_asset_reserve > asset_ceiling {
+            log::error!(
+                target: LOG_TARGET,
+                "Asset overflow: {:?} > {:?} + {:?}",
+                temporary_asset_reserve,
+                pair.token_reserve,
+                routed_asset
+            );
+            return Err(Error::<T>::AssetOverflow);
+        }
+        Ok(SettlementSimulation {
+            currency_reserve: temporary_currency_reserve,
+            asset_reserve: temporary_asset_reserve,
+            netted_currency,
+            netted_asset,
+            routed_currency,
+            routed_asset,
+        })
+    }
+
+    /// Clear the batch at the uniform price `p` that keeps the product constant:
+    ///
+    /// (x + C - A * p) * (y + A - C / p) = x * y
+    ///
+    /// Besides `p = C / A`, where the pool doesn't trade at all, the root is
+    /// `p = (x + C) / (y + A)`. The reserves after the batch are
+    ///
+    /// x' = y * (x + C) / (y + A)
+    /// y' = x * (y + A) / (x + C)
+    ///
+    /// both rounded up in favour of the pool.
+    /// Returns the (currency reserve, asset reserve) after the batch.
+    pub(crate) fn clear_batch(
+        pair: &PairOf<T>,
+        modified_cumulative_currency: BalanceOf<T>,
+        modified_cumulative_asset: BalanceOf<T>,
+    ) -> Result<(BalanceOf<T>, BalanceOf<T>), Error<T>> {
+        let currency_reserve = pair.currency_reserve;
+        let asset_reserve = T::asset_to_currency(pair.token_reserve);
+        let currency_total = currency_reserve
+            .checked_add(&modified_cumulative_currency)
+            .ok_or(Error::<T>::Overflow)?;
+        let asset_total = asset_reserve
+            .checked_add(&modified_cumulative_asset)
+            .ok_or(Error::<T>::Overflow)?;
+        let div_ceil = |numerator: BalanceOf<T>, denominator: BalanceOf<T>| {
+            let quotient = numerator / denominator;
+            if (numerator % denominator).is_zero() { quotient } else { quotient + One::one() }
+        };
+        Ok((
+            div_ceil(
+                asset_reserve.checked_mul(&currency_total).ok_or(Error::<T>::Overflow)?,
+                asset_total,
+            ),
+            div_ceil(
+                currency_reserve.checked_mul(&asset_total).ok_or(Error::<T>::Overflow)?,
+                currency_total,
+            ),
+        ))
+    }
+
+    /// Push the batch through the curve in `Fragment` steps, `first` trading
+    /// first in every step.
+    /// Returns the (currency reserve, asset reserve) after the batch.
+    pub(crate) fn simulate_fragments(
+        pair: &PairOf<T>,
+        modified_cumulative_currency: BalanceOf<T>,
+        modified_cumulative_asset: BalanceOf<T>,
+        first: SwapDirection,
+    ) -> (BalanceOf<T>, BalanceOf<T>) {
+        // Temporary reserves to save gas
+        let mut temporary_currency_reserve = pair.currency_reserve;
+        let mut temporary_asset_reserve = T::asset_to_currency(pair.token_reserve);
+        let constant_product = temporary_currency_reserve * temporary_asset_reserve;
+
+        let fragment = T::Fragment::get();
+        for _i in 1..=fragment {
+            match first {
+                SwapDirection::CurrencyToAsset => {
+                    // Base currency increase and quote currency decrease
+                    temporary_currency_reserve += modified_cumulative_currency / fragment.into();
+                    temporary_asset_reserve = constant_product / temporary_currency_reserve;
+
+                    // Quote currency increase and base currency decrease
+                    temporary_asset_reserve += modified_cumulative_asset / fragment.into();
+                    temporary_currency_reserve = constant_product / temporary_asset_reserve;
+                }
+                SwapDirection::AssetToCurrency => {
+                    // Quote currency increase and base currency decrease
+                    temporary_asset_reserve += modified_cumulative_asset / fragment.into();
+                    temporary_currency_reserve = constant_product / temporary_asset_reserve;
+
+                    // Base currency increase and quote currency decrease
+                    temporary_currency_reserve += modified_cumulative_currency / fragment.into();
+                    temporary_asset_reserve = constant_product / temporary_currency_reserve;
+                }
+            }
+        }
+        (temporary_currency_reserve, temporary_asset_reserve)
+    }
+
+    /// The currency to asset intents of the next batch, oldest first.
+    /// The batch stops at the first intent queued since the seed shuffling it
+    /// is known, its owner could have known its place in the distribution.
+    /// Returns the (live intents, intents past their deadline queued before them).
+    pub(crate) fn batch_currency_intents(
+        asset_id: &AssetIdOf<T>,
+        now: BlockNumberFor<T>,
+    ) -> (
+        Vec<(IntentId, CurrencyToAssetIntentOf<T>)>,
+        Vec<(IntentId, CurrencyToAssetIntentOf<T>)>,
+    ) {
+        let bidding = <BiddingIntents<T>>::get(asset_id.clone());
+        let (_, known_since) = Self::batch_seed(asset_id, SwapDirection::CurrencyToAsset);
+        let intents = Self::queued_intents(asset_id, SwapDirection::CurrencyToAsset)
+            .take_while(|intent_id| {
+                bidding.as_ref().is_none_or(|(buys, _)| buys.contains(intent_id))
+            })
+            .filter_map(|intent_id| {
+                <CurrencyToAssetIntents<T>>::get(intent_id).map(|intent| (intent_id, intent))
+            })
+            .take_while(|(_, intent)| intent.queued_at < known_since);
+        Self::split_expired(intents, now, |intent| intent.deadline)
+    }
+
+    /// The asset to currency intents of the next batch, oldest first.
+    /// The batch stops like in `batch_currency_intents`.
+    /// Returns the (live intents, intents past their deadline queued before them).
+    pub(crate) fn batch_asset_intents(
+        asset_id: &AssetIdOf<T>,
+        now: BlockNumberFor<T>,
+    ) -> (
+        Vec<(IntentId, AssetToCurrencyIntentOf<T>)>,
+        Vec<(IntentId, AssetToCurrencyIntentOf<T>)>,
+    ) {
+        let bidding = <BiddingIntents<T>>::get(asset_id.clone());
+        let (_, known_since) = Self::batch_seed(asset_id, SwapDirection::AssetToCurrency);
+        let intents = Self::queued_intents(asset_id, SwapDirection::AssetToCurrency)
+            .take_while(|intent_id| {
+                bidding.as_ref().is_none_or(|(_, sells)| sells.contains(intent_id))
+            })
+            .filter_map(|intent_id| {
+                <AssetToCurrencyIntents<T>>::get(intent_id).map(|intent| (intent_id, intent))
+            })
+            .take_while(|(_, intent)| intent.queued_at < known_since);
+        Self::split_expired(intents, now, |intent| intent.deadline)
+    }
+
+    /// Take queued intents until `MinQueueAmount` live ones are found,
+    /// setting aside the expired ones on the way. At most `MinQueueAmount`
+    /// expired intents are taken, the rest are refunded by the next batches.
+    fn split_expired<I>(
+        intents: impl Iterator<Item = (IntentId, I)>,
+        now: BlockNumberFor<T>,
+        deadline: impl Fn(&I) -> BlockNumberFor<T>,
+    ) -> (Vec<(IntentId, I)>, Vec<(IntentId, I)>) {
+        let batch_size = T::MinQueueAmount::get() as usize;
+        let mut live = Vec::new();
+        let mut expired = Vec::new();
+        for (intent_id, intent) in intents {
+            if live.len() >= batch_size || expired.len() >= batch_size {
+                break;
+            }
+            if deadline(&intent) < now {
+                expired.push((intent_id, intent));
+            } else {
+                live.push((intent_id, intent));
+            }
+        }
+        (live, expired)
+    }
+
+    /// The hash a swap intent is committed under.
+    pub fn commitment_of(
+        asset_id: &AssetIdOf<T>,
+        direction: SwapDirection,
+        amount_in: BalanceOf<T>,
+        min_output: BalanceOf<T>,
+        deadline: BlockNumberFor<T>,
+        salt: &[u8; 32],
+    ) -> T::Hash {
+        T::Hashing::hash_of(&(asset_id, direction, amount_in, min_output, deadline, salt))
+    }
+
+    /// Lock the currency of `caller` in the pallet account and queue the swap.
+    /// The swap is refunded at settlement if it pays less than its minimum output
+    /// or if `deadline` has passed.
+    pub(crate) fn queue_swap_currency_for_asset(
+        caller: T::AccountId,
+        asset_id: AssetIdOf<T>,
+        swap: AntiMevSwap<BalanceOf<T>, AssetBalanceOf<T>>,
+        deadline: BlockNumberFor<T>,
+    ) -> DispatchResult {
+        let amount_in = swap.locked_input();
+        ensure!(!amount_in.is_zero(), Error::<T>::TradeAmountIsZero);
+        Self::check_enough_currency(&caller, &amount_in)?;
+
+        let pair = Self::get_pair(&asset_id)?;
+
+        // pre compute to make sure the trade is possible
+        match swap {
+            AntiMevSwap::BasedInput { input_amount, .. } => {
+                Self::cp_get_output_amount(
+                    &input_amount,
+                    &pair.currency_reserve,
+                    &T::asset_to_currency(pair.token_reserve),
+                )?;
+            }
+            AntiMevSwap::BasedOutput { output_amount, .. } => {
+                ensure!(!output_amount.is_zero(), Error::<T>::TradeAmountIsZero);
+                Self::cp_get_input_amount(
+                    &T::asset_to_currency(output_amount),
+                    &pair.currency_reserve,
+                    &T::asset_to_currency(pair.token_reserve),
+                )?;
+            }
+        }
+
+        // transfer to pallet account
+        let pallet_account = T::pallet_account();
+        <T as pallet::Config>::Currency::transfer(
+            &caller,
+            &pallet_account,
+            amount_in,
+            ExistenceRequirement::KeepAlive,
+        )?;
+
+        // add tx to queue
+        Self::add_currency_to_asset_tx(
+            asset_id,
+            swap,
+            deadline,
+            caller,
+        )?;
+
+        Ok(())
+    }
+
+    /// Lock the asset of `caller` in the pallet account and queue the swap.
+    /// The swap is refunded at settlement if it pays less than its minimum output
+    /// or if `deadline` has passed.
+    pub(crate) fn queue_swap_asset_for_currency(
+        caller: T::AccountId,
+        asset_id: AssetIdOf<T>,
+        swap: AntiMevSwap<AssetBalanceOf<T>, BalanceOf<T>>,
+        deadline: BlockNumberFor<T>,
+    ) -> DispatchResult {
+        let amount_in = swap.locked_input();
+        ensure!(!amount_in.is_zero(), Error::<T>::TradeAmountIsZero);
+        Self::check_enough_tokens(&asset_id, &caller, &amount_in)?;
+
+        let pair = Self::get_pair(&asset_id)?;
+
+        // pre compute to make sure the trade is possible
+        match swap {
+            AntiMevSwap::BasedInput { input_amount, .. } => {
+                Self::cp_get_output_amount(
+                    &T::asset_to_currency(input_amount),
+                    &T::asset_to_currency(pair.token_reserve),
+                    &pair.currency_reserve,
+                )?;
+            }
+            AntiMevSwap::BasedOutput { output_amount, .. } => {
+                ensure!(!output_amount.is_zero(), Error::<T>::TradeAmountIsZero);
+                Self::cp_get_input_amount(
+                    &output_amount,
+                    &T::asset_to_currency(pair.token_reserve),
+                    &pair.currency_reserve,
+                )?;
+            }
+        }
+
+        // transfer to pallet account
+        let pallet_account = T::pallet_account();
+        T::Assets::transfer(
             asset_id.clone(),
-            recipient.clone(),
-            recipient.clone(),
-            amount_out,
+            &caller,
+            &pallet_account,
             amount_in,
-        ));
+            Preservation::Preserve,
+        )?;
+
+        // add tx to queue
+        Self::add_asset_to_currency_tx(
+            asset_id,
+            swap,
+            deadline,
+            caller,
+        )?;
+
+        Ok(())
+    }
+
+    /// Lock the sold asset of `caller` in the pallet account and queue its sale,
+    /// the currency it settles for is queued to buy `bought_asset_id`.
+    pub(crate) fn queue_swap_asset_for_asset(
+        caller: T::AccountId,
+        sold_asset_id: AssetIdOf<T>,
+        bought_asset_id: AssetIdOf<T>,
+        amount_in: AssetBalanceOf<T>,
+        min_output: AssetBalanceOf<T>,
+        deadline: BlockNumberFor<T>,
+    ) -> DispatchResult {
+        ensure!(!amount_in.is_zero(), Error::<T>::TradeAmountIsZero);
+        Self::check_enough_tokens(&sold_asset_id, &caller, &amount_in)?;
+
+        let sold_pair = Self::get_pair(&sold_asset_id)?;
+
+        // pre compute to make sure the trade is possible
+        let currency_output = Self::cp_get_output_amount(
+            &T::asset_to_currency(amount_in),
+            &T::asset_to_currency(sold_pair.token_reserve),
+            &sold_pair.currency_reserve,
+        )?;
+
+        let bought_pair = Self::get_pair(&bought_asset_id)?;
+
+        // pre compute to make sure the trade is possible, the buy leg is
+        // queued with the currency the sell leg actually produces
+        Self::cp_get_output_amount(
+            &currency_output,
+            &bought_pair.currency_reserve,
+            &T::asset_to_currency(bought_pair.token_reserve),
+        )?;
+
+        // transfer to pallet account
+        let pallet_account = T::pallet_account();
+        T::Assets::transfer(
+            sold_asset_id.clone(),
+            &caller,
+            &pallet_account,
+            amount_in,
+            Preservation::Expendable,
+        )?;
+        
+        // add tx to queue
+        let intent_id = Self::add_asset_to_currency_tx(
+            sold_asset_id,
+            AntiMevSwap::BasedInput { input_amount: amount_in, min_output: Zero::zero() },
+            deadline,
+            caller,
+        )?;
+        // the buy leg is bound by the same deadline
+        <NextLegs<T>>::insert(intent_id, (bought_asset_id, min_output, deadline));
+
+        Ok(())
+    }
+
+    /// Refund the locked input of an intent that is dropped from its batch
+    /// through its claim, `event` tells why it was dropped.
+    pub(crate) fn do_anti_mev_refund_currency(
+        asset_id: &AssetIdOf<T>,
+        intent_id: IntentId,
+        intent: &CurrencyToAssetIntentOf<T>,
+        event: fn(AssetIdOf<T>, IntentId, T::AccountId, BalanceOf<T>) -> Event<T>,
+    ) -> DispatchResult {
+        let recipient = &intent.owner;
+        let amount_in: BalanceOf<T> = intent.swap.locked_input();
+        Self::record_claim(asset_id, intent_id, recipient, amount_in, Zero::zero());
+        Self::deposit_event(event(asset_id.clone(), intent_id, recipient.clone(), amount_in));
+        Ok(())
+    }
+
+    /// Refund the locked input of an intent that is dropped from its batch
+    /// through its claim, `event` tells why it was dropped.
+    pub(crate) fn do_anti_mev_refund_asset(
+        asset_id: &AssetIdOf<T>,
+        intent_id: IntentId,
+        intent: &AssetToCurrencyIntentOf<T>,
+        event: fn(AssetIdOf<T>, IntentId, T::AccountId, AssetBalanceOf<T>) -> Event<T>,
+    ) -> DispatchResult {
+        let recipient = &intent.owner;
+        let amount_in: AssetBalanceOf<T> = intent.swap.locked_input();
+        Self::record_claim(asset_id, intent_id, recipient, Zero::zero(), amount_in);
+        Self::deposit_event(event(asset_id.clone(), intent_id, recipient.clone(), amount_in));
+        Ok(())
+    }
+
+    /// Remove every queued intent of `account_id` in one direction
+    /// and refund the deposited amounts.
+    pub(crate) fn do_cancel_swap_intent(
+        asset_id: &AssetIdOf<T>,
+        account_id: &T::AccountId,
+        direction: SwapDirection,
+    ) -> DispatchResult {
+        let pallet_account = T::pallet_account();
+        let mut cancelled: Vec<u32> = Vec::new();
+        match direction {
+            SwapDirection::CurrencyToAsset => {
+                for (slot, intent_id) in Self::queued_slots(asset_id, direction) {
+                    let Some(intent) = <CurrencyToAssetIntents<T>>::get(intent_id) else {
+                        continue;
+                    };
+                    if intent.owner != *account_id {
+                        continue;
+                    }
+                    <CurrencyToAssetIntents<T>>::remove(intent_id);
+                    <PendingInputs<T>>::mutate(asset_id.clone(), |(currency, _)| {
+                        currency.saturating_reduce(intent.swap.locked_input())
+                    });
+                    <T as pallet::Config>::Currency::transfer(
+                        &pallet_account,
+                        account_id,
+                        intent.swap.locked_input(),
+                        ExistenceRequirement::AllowDeath,
+                    )?;
+                    Self::deposit_event(Event::CancelledSwapCurrencyForAsset(
+                        asset_id.clone(),
+                        intent_id,
+                        account_id.clone(),
+                        intent.swap.locked_input(),
+                    ));
+                    cancelled.push(slot);
+                }
+            }
+            SwapDirection::AssetToCurrency => {
+                for (slot, intent_id) in Self::queued_slots(asset_id, direction) {
+                    let Some(intent) = <AssetToCurrencyIntents<T>>::get(intent_id) else {
+                        continue;
+                    };
+                    if intent.owner != *account_id {
+                        continue;
+                    }
+                    <AssetToCurrencyIntents<T>>::remove(intent_id);
+                    <NextLegs<T>>::remove(intent_id);
+                    <PendingInputs<T>>::mutate(asset_id.clone(), |(_, asset)| {
+                        asset.saturating_reduce(intent.swap.locked_input())
+                    });
+                    T::Assets::transfer(
+                        asset_id.clone(),
+                        &pallet_account,
+                        account_id,
+                        intent.swap.locked_input(),
+                        Preservation::Expendable,
+                    )?;
+                    Self::deposit_event(Event::CancelledSwapAssetForCurrency(
+                        asset_id.clone(),
+                        intent_id,
+                        account_id.clone(),
+                        intent.swap.locked_input(),
+                    ));
+                    cancelled.push(slot);
+                }
+            }
+        }
+        ensure!(!cancelled.is_empty(), Error::<T>::SwapIntentNotFound);
+        Self::remove_from_queue(asset_id, direction, &cancelled);
+
+        // an empty queue starts a new epoch with its next intent
+        if !Self::has_pending_intents(asset_id) {
+            <EpochStart<T>>::remove(asset_id.clone());
+        }
         Ok(())
     }
 
     pub(crate) fn add_currency_to_asset_tx(
         asset_id: T::AssetId,
-        amount_in: BalanceOf<T>,
+        swap: AntiMevSwap<BalanceOf<T>, AssetBalanceOf<T>>,
+        deadline: BlockNumberFor<T>,
         buyer: T::AccountId,
-        mut pair_currency_cumulative: BalanceOf<T>,
-        mut pair_currency_queue: Vec<T::AccountId>,
-    ) -> Result<(), Error<T>> {
-        pair_currency_queue.push(buyer.clone());
-        <CurrencyToAssetQueue<T>>::insert(asset_id.clone(), pair_currency_queue);
-        
-        pair_currency_cumulative.saturating_accrue(amount_in);
-        <CurrencyToAssetCumulative<T>>::insert(asset_id.clone(), buyer.clone(), pair_currency_cumulative);
+    ) -> Result<IntentId, Error<T>> {
+        let amount_in = swap.locked_input();
+        let intent_id = Self::next_intent();
+        Self::push_to_queue(&asset_id, SwapDirection::CurrencyToAsset, intent_id)?;
+        <PendingInputs<T>>::mutate(asset_id.clone(), |(currency, _)| currency.saturating_accrue(amount_in));
+        <EpochStart<T>>::mutate(asset_id.clone(), |start| {
+            start.get_or_insert_with(<frame_system::Pallet<T>>::block_number);
+        });
+
+        <CurrencyToAssetIntents<T>>::insert(intent_id, Intent {
+            owner: buyer.clone(),
+            swap,
+            deadline,
+            queued_at: <frame_system::Pallet<T>>::block_number(),
+        });
 
         // emit event
         Self::deposit_event(Event::AddedSwapCurrencyForAsset(
             asset_id,
+            intent_id,
             buyer,
             amount_in,
         ));
-        Ok(())
+        Ok(intent_id)
     }
    
     pub(crate) fn add_asset_to_currency_tx(
         asset_id: T::AssetId,
-        amount_in: AssetBalanceOf<T>,
+        swap: AntiMevSwap<AssetBalanceOf<T>, BalanceOf<T>>,
+        deadline: BlockNumberFor<T>,
         buyer: T::AccountId,
-        mut pair_asset_cumulative: AssetBalanceOf<T>,
-        mut pair_asset_queue: Vec<T::AccountId>,
-    ) -> Result<(), Error<T>> {
-        pair_asset_queue.push(buyer.clone());
-        <AssetToCurrencyQueue<T>>::insert(asset_id.clone(), pair_asset_queue);
-        
-        pair_asset_cumulative.saturating_accrue(amount_in);
-        <AssetToCurrencyCumulative<T>>::insert(asset_id.clone(), buyer.clone(), pair_asset_cumulative);
+    ) -> Result<IntentId, Error<T>> {
+        let amount_in = swap.locked_input();
+        let intent_id = Self::next_intent();
+        Self::push_to_queue(&asset_id, SwapDirection::AssetToCurrency, intent_id)?;
+        <PendingInputs<T>>::mutate(asset_id.clone(), |(_, asset)| asset.saturating_accrue(amount_in));
+        <EpochStart<T>>::mutate(asset_id.clone(), |start| {
+            start.get_or_insert_with(<frame_system::Pallet<T>>::block_number);
+        });
+
+        <AssetToCurrencyIntents<T>>::insert(intent_id, Intent {
+            owner: buyer.clone(),
+            swap,
+            deadline,
+            queued_at: <frame_system::Pallet<T>>::block_number(),
+        });
 
         // emit event
         Self::deposit_event(Event::AddedSwapAssetForCurrency(
             asset_id,
+            intent_id,
             buyer,
             amount_in,
         ));
-        Ok(())
+        Ok(intent_id)
+    }
+
+    fn next_intent() -> IntentId {
+        <NextIntentId<T>>::mutate(|next| {
+            let intent_id = *next;
+            next.saturating_inc();
+            intent_id
+        })
     }
  
-    pub(crate) fn get_pair_currency_cumulative(
+    #[cfg(test)]
+    pub (crate) fn get_pair_currency_queue(
         asset_id: &AssetIdOf<T>,
-        account_id: &T::AccountId,
-    ) -> BalanceOf<T> {
-        <CurrencyToAssetCumulative<T>>::get(asset_id.clone(), account_id.clone())
-            .unwrap_or_default()
+    ) -> Result<Vec<IntentId>, Error<T>> {
+        ensure!(<Pairs<T>>::contains_key(asset_id.clone()), Error::<T>::PairNotFound);
+        Ok(Self::queued_intents(asset_id, SwapDirection::CurrencyToAsset).collect())
     }
 
-    pub(crate) fn get_pair_asset_cumulative(
+    #[cfg(test)]
+    pub(crate) fn get_pair_asset_queue(
         asset_id: &AssetIdOf<T>,
-        account_id: &T::AccountId,
-    ) -> AssetBalanceOf<T> {
-        <AssetToCurrencyCumulative<T>>::get(asset_id.clone(), account_id.clone())
-            .unwrap_or_default()
+    ) -> Result<Vec<IntentId>, Error<T>> {
+        ensure!(<Pairs<T>>::contains_key(asset_id.clone()), Error::<T>::PairNotFound);
+        Ok(Self::queued_intents(asset_id, SwapDirection::AssetToCurrency).collect())
     }
 
-    pub (crate) fn get_pair_currency_queue(
+    pub(crate) fn has_pending_intents(asset_id: &AssetIdOf<T>) -> bool {
+        !<QueueIndices<T>>::get(asset_id.clone(), SwapDirection::CurrencyToAsset).is_empty()
+            || !<QueueIndices<T>>::get(asset_id.clone(), SwapDirection::AssetToCurrency).is_empty()
+    }
+
+    pub(crate) fn queue_len(asset_id: &AssetIdOf<T>, direction: SwapDirection) -> u32 {
+        <QueueIndices<T>>::get(asset_id.clone(), direction).len()
+    }
+
+    /// The queued intents of one direction, oldest first.
+    /// Entries are read from the head as the iterator advances.
+    pub(crate) fn queued_intents(
         asset_id: &AssetIdOf<T>,
-    ) -> Result<Vec<T::AccountId>, Error<T>> {
-        <CurrencyToAssetQueue<T>>::get(asset_id.clone())
-            .ok_or(Error::PairNotFound)
+        direction: SwapDirection,
+    ) -> impl Iterator<Item = IntentId> {
+        Self::queued_slots(asset_id, direction).map(|(_, intent_id)| intent_id)
     }
 
-    pub(crate) fn get_pair_asset_queue(
+    /// The queued intents of one direction with their queue slots, oldest first.
+    /// The slots of cancelled intents are empty and skipped.
+    pub(crate) fn queued_slots(
         asset_id: &AssetIdOf<T>,
-    ) -> Result<Vec<T::AccountId>, Error<T>> {
-        <AssetToCurrencyQueue<T>>::get(asset_id.clone())
-            .ok_or(Error::PairNotFound)
+        direction: SwapDirection,
+    ) -> impl Iterator<Item = (u32, IntentId)> {
+        let asset_id = asset_id.clone();
+        let index = <QueueIndices<T>>::get(asset_id.clone(), direction);
+        (0..index.len()).filter_map(move |offset| {
+            let slot = index.head.wrapping_add(offset);
+            <Queues<T>>::get((asset_id.clone(), direction, slot)).map(|intent_id| (slot, intent_id))
+        })
+    }
+
+    /// Append `intent_id` to the tail of the queue in O(1).
+    pub(crate) fn push_to_queue(
+        asset_id: &AssetIdOf<T>,
+        direction: SwapDirection,
+        intent_id: IntentId,
+    ) -> Result<(), Error<T>> {
+        <QueueIndices<T>>::try_mutate(asset_id.clone(), direction, |index| {
+            ensure!(index.len() < T::MaxQueueLength::get(), Error::<T>::QueueFull);
+            <Queues<T>>::insert((asset_id.clone(), direction, index.tail), intent_id);
+            index.tail = index.tail.wrapping_add(1);
+            Ok(())
+        })
+    }
+
+    /// Remove the first `count` intents from the head of the queue,
+    /// along with the empty slots between them.
+    pub(crate) fn pop_from_queue(asset_id: &AssetIdOf<T>, direction: SwapDirection, count: u32) {
+        let mut index = <QueueIndices<T>>::get(asset_id.clone(), direction);
+        let mut popped = 0;
+        while popped < count && !index.is_empty() {
+            if <Queues<T>>::take((asset_id.clone(), direction, index.head)).is_some() {
+                popped += 1;
+            }
+            index.head = index.head.wrapping_add(1);
+        }
+        Self::store_queue_index(asset_id, direction, index);
+    }
+
+    /// Empty the queue `slots` in O(1) each, keeping the order of the rest.
+    /// Empty slots at either end of the queue are dropped, the others are
+    /// skipped when the queue is read.
+    pub(crate) fn remove_from_queue(
+        asset_id: &AssetIdOf<T>,
+        direction: SwapDirection,
+        slots: &[u32],
+    ) {
+        for slot in slots {
+            <Queues<T>>::remove((asset_id.clone(), direction, *slot));
+        }
+        Self::store_queue_index(asset_id, direction, <QueueIndices<T>>::get(asset_id.clone(), direction));
+    }
+
+    /// Store the queue index with the empty slots at its ends dropped.
+    fn store_queue_index(asset_id: &AssetIdOf<T>, direction: SwapDirection, mut index: QueueIndex) {
+        while !index.is_empty() && !<Queues<T>>::contains_key((asset_id.clone(), direction, index.head)) {
+            index.head = index.head.wrapping_add(1);
+        }
+        while !index.is_empty()
+            && !<Queues<T>>::contains_key((asset_id.clone(), direction, index.tail.wrapping_sub(1)))
+        {
+            index.tail = index.tail.wrapping_sub(1);
+        }
+        if index.is_empty() {
+            <QueueIndices<T>>::remove(asset_id.clone(), direction);
+        } else {
+            <QueueIndices<T>>::insert(asset_id.clone(), direction, index);
+        }
+    }
+
+    /// Currency held by the pallet account for queued currency to asset swaps.
+    pub(crate) fn pending_currency(asset_id: &AssetIdOf<T>) -> BalanceOf<T> {
+        <PendingInputs<T>>::get(asset_id.clone()).0
+    }
+
+    /// Asset held by the pallet account for queued asset to currency swaps.
+    pub(crate) fn pending_asset(asset_id: &AssetIdOf<T>) -> AssetBalanceOf<T> {
+        <PendingInputs<T>>::get(asset_id.clone()).1
     }
-}
\ No newline at end of file
+}
@@ -1,35 +1,121 @@
-//! Benchmarking setup for pallet-template
+//! Benchmarking setup for pallet-anti-mev-amm
 #![cfg(feature = "runtime-benchmarks")]
 use super::*;
 
-#[allow(unused)]
-use crate::Pallet as Template;
+use crate::Pallet as AntiMevAmm;
 use frame_benchmarking::v2::*;
 use frame_system::RawOrigin;
 
+const SEED: u32 = 0;
+
+/// An account holding `amount` of currency and of the asset.
+fn funded_account<T: Config>(
+    name: &'static str,
+    asset_id: &AssetIdOf<T>,
+    amount: BalanceOf<T>,
+) -> Result<T::AccountId, BenchmarkError> {
+    let who: T::AccountId = account(name, 0, SEED);
+    <T as Config>::Currency::make_free_balance_be(&who, amount);
+    T::Assets::mint_into(asset_id.clone(), &who, T::currency_to_asset(amount))?;
+    Ok(who)
+}
+
+/// The most intents a batch takes in each direction, `MinQueueAmount` live
+/// ones behind at most `MinQueueAmount - 1` expired ones, see `split_expired`.
+fn max_batch_intents<T: Config>() -> u32 {
+    T::MinQueueAmount::get().saturating_mul(2).saturating_sub(1)
+}
+
 #[benchmarks]
 mod benchmarks {
-	use super::*;
+    use super::*;
+
+    // `s` empty slots are read in the currency to asset queue, `i` intents
+    // are settled, live and expired, in both directions
+    #[benchmark]
+    fn settle_and_distribute(
+        s: Linear<0, { T::MaxQueueLength::get().saturating_sub(max_batch_intents::<T>()) }>,
+        i: Linear<3, { max_batch_intents::<T>().saturating_mul(2) }>,
+    ) -> Result<(), BenchmarkError> {
+        let unit = <T as Config>::Currency::minimum_balance().max(One::one());
+        let liquidity = unit.saturating_mul(1_000_000u32.into());
+        let amount_in = unit.saturating_mul(1_000u32.into());
+        let asset_id = T::BenchmarkHelper::asset_id(1);
+        T::BenchmarkHelper::create_asset(&asset_id);
+        let provider =
+            funded_account::<T>("provider", &asset_id, liquidity.saturating_mul(2u32.into()))?;
+        let trader = funded_account::<T>("trader", &asset_id, liquidity)?;
+        let canceller = funded_account::<T>("canceller", &asset_id, liquidity)?;
+        AntiMevAmm::<T>::create_pair(
+            RawOrigin::Signed(provider).into(),
+            asset_id.clone(),
+            T::BenchmarkHelper::asset_id(2),
+            liquidity,
+            T::currency_to_asset(liquidity),
+        )?;
 
-	// #[benchmark]
-	// fn do_something() {
-	// 	let value = 100u32.into();
-	// 	let caller: T::AccountId = whitelisted_caller();
-	// 	#[extrinsic_call]
-	// 	do_something(RawOrigin::Signed(caller), value);
+        // the intents are dealt to (live buys, live sells, expired buys, expired sells)
+        let count = |bucket: u32| i.saturating_add(3).saturating_sub(bucket) / 4;
+        let now = <frame_system::Pallet<T>>::block_number();
+        let live = now
+            .saturating_add(T::EpochLength::get())
+            .saturating_add(T::BiddingPeriod::get());
+        let buy = AntiMevSwap::BasedInput { input_amount: amount_in, min_output: One::one() };
+        let sell = AntiMevSwap::BasedInput {
+            input_amount: T::currency_to_asset(amount_in),
+            min_output: One::one(),
+        };
+        let buy_deadlines = (0..count(2)).map(|_| now).chain((0..count(0)).map(|_| live));
+        for (index, deadline) in buy_deadlines.enumerate() {
+            AntiMevAmm::<T>::queue_swap_currency_for_asset(
+                trader.clone(),
+                asset_id.clone(),
+                buy.clone(),
+                deadline,
+            )?;
+            // the cancelled intents leave empty slots behind the first one
+            if index == 0 {
+                for _ in 0..s {
+                    AntiMevAmm::<T>::queue_swap_currency_for_asset(
+                        canceller.clone(),
+                        asset_id.clone(),
+                        buy.clone(),
+                        live,
+                    )?;
+                }
+            }
+        }
+        if s > 0 {
+            AntiMevAmm::<T>::do_cancel_swap_intent(
+                &asset_id,
+                &canceller,
+                SwapDirection::CurrencyToAsset,
+            )?;
+        }
+        let sell_deadlines = (0..count(3)).map(|_| now).chain((0..count(1)).map(|_| live));
+        for deadline in sell_deadlines {
+            AntiMevAmm::<T>::queue_swap_asset_for_currency(
+                trader.clone(),
+                asset_id.clone(),
+                sell.clone(),
+                deadline,
+            )?;
+        }
 
-	// 	assert_eq!(Something::<T>::get(), Some(value));
-	// }
+        // the batch is mature, and left to the pallet when solvers may bid
+        let epoch_end = now.saturating_add(T::EpochLength::get());
+        frame_system::Pallet::<T>::set_block_number(epoch_end);
+        if !T::BiddingPeriod::get().is_zero() {
+            let bidding_end = AntiMevAmm::<T>::open_bidding(&asset_id, epoch_end);
+            frame_system::Pallet::<T>::set_block_number(bidding_end);
+        }
 
-	// #[benchmark]
-	// fn cause_error() {
-	// 	Something::<T>::put(100u32);
-	// 	let caller: T::AccountId = whitelisted_caller();
-	// 	#[extrinsic_call]
-	// 	cause_error(RawOrigin::Signed(caller));
+        #[extrinsic_call]
+        settle_and_distribute(RawOrigin::Signed(trader), asset_id.clone());
 
-	// 	assert_eq!(Something::<T>::get(), Some(101u32));
-	// }
+        assert!(!AntiMevAmm::<T>::has_pending_intents(&asset_id));
+        Ok(())
+    }
 
-	// impl_benchmark_test_suite!(Template, crate::mock::new_test_ext(), crate::mock::Test);
+    impl_benchmark_test_suite!(AntiMevAmm, crate::mock::new_test_ext(), crate::mock::TestRuntime);
 }
@@ -28,8 +28,10 @@ impl<T: Config> Pallet<T> {
         )?;
 
         // update pair balances
+        let reference_price = Self::reference_price(&pair);
         pair.currency_reserve.saturating_accrue(currency_amount);
         pair.token_reserve.saturating_reduce(token_amount);
+        Self::check_price_band(reference_price, &pair)?;
         <Pairs<T>>::insert(asset_id.clone(), pair);
 
         // emit event
@@ -53,25 +55,28 @@ impl<T: Config> Pallet<T> {
     ) -> DispatchResult {
         let asset_id = pair.asset_id.clone();
         let pallet_account = T::pallet_account();
+        // the seller pays the asset into the pair, the currency is paid out of it
+        T::Assets::transfer(
+            asset_id.clone(),
+            &buyer,
+            &pallet_account,
+            token_amount,
+            Preservation::Expendable,
+        )?;
         if recipient != pallet_account {
             <T as pallet::Config>::Currency::transfer(
-                &buyer,
                 &pallet_account,
+                &recipient,
                 currency_amount,
                 ExistenceRequirement::AllowDeath,
             )?;
         }
-        T::Assets::transfer(
-            asset_id.clone(),
-            &pallet_account,
-            &buyer,
-            token_amount,
-            Preservation::Expendable,
-        )?;
 
         // update pair balances
+        let reference_price = Self::reference_price(&pair);
         pair.currency_reserve.saturating_reduce(currency_amount);
         pair.token_reserve.saturating_accrue(token_amount);
+        Self::check_price_band(reference_price, &pair)?;
         <Pairs<T>>::insert(asset_id.clone(), pair);
 
         // emit event
@@ -111,6 +116,54 @@ impl<T: Config> Pallet<T> {
         )
     }
 
+    /// The price of the pair before the first instant swap of the block, recorded
+    /// by that swap. `None` when the instant swaps are unguarded.
+    pub(crate) fn reference_price(pair: &PairOf<T>) -> Option<FixedU128> {
+        T::PriceBand::get()?;
+        let now = <frame_system::Pallet<T>>::block_number();
+        match <ReferencePrices<T>>::get(pair.asset_id.clone()) {
+            Some((block, price)) if block == now => Some(price),
+            _ => {
+                let price = Self::spot_price(pair);
+                <ReferencePrices<T>>::insert(pair.asset_id.clone(), (now, price));
+                Some(price)
+            },
+        }
+    }
+
+    /// Reject an instant swap leaving the price of the pair further than
+    /// `PriceBand` from its reference price. Sandwiching a trade takes moving
+    /// the price twice in the same block, so both legs stay within the band.
+    pub(crate) fn check_price_band(
+        reference_price: Option<FixedU128>,
+        pair: &PairOf<T>,
+    ) -> Result<(), Error<T>> {
+        let (Some(reference_price), Some(band)) = (reference_price, T::PriceBand::get()) else {
+            return Ok(());
+        };
+        ensure!(
+            Self::price_impact(reference_price, Self::spot_price(pair))
+                <= FixedU128::from_perbill(band),
+            Error::<T>::PriceBandExceeded
+        );
+        Ok(())
+    }
+
+    /// The move from `price` to `new_price`, relative to `price`.
+    pub(crate) fn price_impact(price: FixedU128, new_price: FixedU128) -> FixedU128 {
+        let deviation = if new_price > price { new_price - price } else { price - new_price };
+        deviation.checked_div(&price).unwrap_or_else(<FixedU128 as Bounded>::max_value)
+    }
+
+    /// The currency paid per asset at the reserves of the pair.
+    pub(crate) fn spot_price(pair: &PairOf<T>) -> FixedU128 {
+        FixedU128::checked_from_rational(
+            T::currency_to_asset(pair.currency_reserve),
+            pair.token_reserve,
+        )
+        .unwrap_or_else(<FixedU128 as Bounded>::max_value)
+    }
+
     pub(crate) fn cp_compute_currency_to_asset(
         pair: &PairOf<T>,
         swap: CpSwap<BalanceOf<T>, AssetBalanceOf<T>>,
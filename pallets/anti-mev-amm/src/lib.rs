@@ -14,27 +14,31 @@ mod tests;
 
 pub mod constant_product;
 pub mod anti_mev;
+pub mod solver;
+pub mod router;
 pub mod types;
+pub mod runtime_api;
 pub mod weights;
 
 use frame_support::{
     pallet_prelude::*,
     sp_runtime::{
         traits::{
-            AccountIdConversion, CheckedAdd, CheckedMul, CheckedSub, Convert, One, Saturating,
-            Zero,
+            AccountIdConversion, Bounded, CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, Convert,
+            IdentifyAccount, One, Saturating, Verify, Zero,
         },
-        FixedPointNumber, FixedPointOperand, FixedU128,
+        ArithmeticError, FixedPointNumber, FixedPointOperand, FixedU128, Perbill,
     },
     traits::{
         fungibles::{Create, Destroy, Inspect, Mutate},
-        tokens::{Balance, Preservation, WithdrawConsequence},
-        ExistenceRequirement, Currency,
+        tokens::{Balance, Fortitude, Precision, Preservation, WithdrawConsequence},
+        ExistenceRequirement, Currency, Randomness, ReservableCurrency,
     },
     transactional, PalletId,
 };
 use codec::EncodeLike;
 use sp_std::{
+    collections::btree_map::BTreeMap,
     vec, vec::Vec,
     fmt::Debug,
 };
@@ -53,7 +57,7 @@ pub type AssetBalanceOf<T> = <T as Config>::AssetBalance;
 /// The log target of this pallet.
 pub const LOG_TARGET: &str = "[💳 Anti MEV AMM]";
 
-#[frame_support::pallet(dev_mode)]
+#[frame_support::pallet]
 pub mod pallet {
 	use super::*;
 	use frame_system::pallet_prelude::*;
@@ -71,7 +75,7 @@ pub mod pallet {
 		type WeightInfo: WeightInfo;
 
         /// The currency trait.
-        type Currency: Currency<Self::AccountId>;
+        type Currency: ReservableCurrency<Self::AccountId>;
 
         /// The balance type for assets (i.e. tokens).
         type AssetBalance: Balance
@@ -120,15 +124,95 @@ pub mod pallet {
         #[pallet::constant]
         type MinInitialToken: Get<AssetBalanceOf<Self>>;
 
+        /// Steps of the fragmented reference settlement simulation
         #[pallet::constant]
         type Fragment: Get<u32>;
         
         /// Maximum queue amount
         type MinQueueAmount: Get<u32>;
+
+        /// Maximum number of intents queued in each direction of a pair
+        #[pallet::constant]
+        type MaxQueueLength: Get<u32>;
+
+        /// Number of blocks a queue may wait before it is settled without being full
+        #[pallet::constant]
+        type EpochLength: Get<BlockNumberFor<Self>>;
+
+        /// Deposit reserved for every committed swap intent
+        #[pallet::constant]
+        type CommitDeposit: Get<BalanceOf<Self>>;
+
+        /// Number of blocks after a commitment before it can be revealed
+        #[pallet::constant]
+        type RevealDelay: Get<BlockNumberFor<Self>>;
+
+        /// Number of blocks during which a commitment can be revealed
+        #[pallet::constant]
+        type RevealPeriod: Get<BlockNumberFor<Self>>;
+
+        /// Part of the deposit forfeited when a commitment is not revealed
+        #[pallet::constant]
+        type CommitForfeit: Get<Perbill>;
+
+        /// Id of the pot paying the executor rewards.
+        #[pallet::constant]
+        type RewardPotId: Get<PalletId>;
+
+        /// Currency paid from the reward pot to the account settling a queue, at most
+        /// the provider fee the settled batch paid
+        #[pallet::constant]
+        type ExecutorReward: Get<BalanceOf<Self>>;
+
+        /// Source of the randomness shuffling the order a batch is distributed in.
+        /// A batch only settles the intents queued before the block its
+        /// randomness is known since, so nobody queues an intent knowing its place
+        type Randomness: Randomness<Self::Hash, BlockNumberFor<Self>>;
+
+        /// The signature of swap intents signed off-chain
+        type OffchainSignature: Verify<Signer = Self::OffchainPublic> + Parameter;
+
+        /// The key signing swap intents off-chain
+        type OffchainPublic: IdentifyAccount<AccountId = Self::AccountId>;
+
+        /// Maximum number of signed intents submitted in one call
+        #[pallet::constant]
+        type MaxSignedIntents: Get<u32>;
+
+        /// Currency reserved from an account registering as a solver
+        #[pallet::constant]
+        type SolverBond: Get<BalanceOf<Self>>;
+
+        /// Number of blocks solvers bid on a mature batch, zero settles without solvers
+        #[pallet::constant]
+        type BiddingPeriod: Get<BlockNumberFor<Self>>;
+
+        /// Currency paid from the reward pot to the solver whose proposal settles a batch
+        #[pallet::constant]
+        type SolverFee: Get<BalanceOf<Self>>;
+
+        /// Number of blocks liquidity tokens can't be removed or transferred after being minted
+        #[pallet::constant]
+        type LiquidityHoldingPeriod: Get<BlockNumberFor<Self>>;
+
+        /// Furthest an instant swap may move the price of a pair from its price at the
+        /// start of the block, `None` leaves the instant swaps unguarded
+        #[pallet::constant]
+        type PriceBand: Get<Option<Perbill>>;
+
+        /// Price impact above which `swap` queues an order into the anti-mev batch,
+        /// for the pairs without their own threshold
+        #[pallet::constant]
+        type DefaultImpactThreshold: Get<Perbill>;
+
+        /// Creates the assets traded in the benchmarks
+        #[cfg(feature = "runtime-benchmarks")]
+        type BenchmarkHelper: BenchmarkHelper<Self::AssetId>;
 	}
 
     pub trait ConfigHelper: Config {
         fn pallet_account() -> AccountIdOf<Self>;
+        fn reward_pot() -> AccountIdOf<Self>;
         fn currency_to_asset(currency_balance: BalanceOf<Self>) -> AssetBalanceOf<Self>;
         fn asset_to_currency(asset_balance: AssetBalanceOf<Self>) -> BalanceOf<Self>;
         fn net_amount_numerator() -> BalanceOf<Self>;
@@ -140,6 +224,11 @@ pub mod pallet {
             Self::PalletId::get().into_account_truncating()
         }
 
+        #[inline(always)]
+        fn reward_pot() -> AccountIdOf<Self> {
+            Self::RewardPotId::get().into_account_truncating()
+        }
+
         #[inline(always)]
         fn currency_to_asset(currency_balance: BalanceOf<Self>) -> AssetBalanceOf<Self> {
             Self::CurrencyToAssetBalance::convert(currency_balance)
@@ -159,6 +248,14 @@ pub mod pallet {
         }
     }
 
+    #[cfg(feature = "runtime-benchmarks")]
+    pub trait BenchmarkHelper<AssetId> {
+        /// The asset id made from `seed`.
+        fn asset_id(seed: u32) -> AssetId;
+        /// Create the asset, so it can be minted to the traders.
+        fn create_asset(asset_id: &AssetId);
+    }
+
     type GenesisPairInfo<T> = (
         // provider
         AccountIdOf<T>,
@@ -253,10 +350,6 @@ pub mod pallet {
                     .saturating_accrue(*currency_amount);
                 pair.token_reserve.saturating_accrue(*token_amount);
                 <Pairs<T>>::insert(asset_id.clone(), pair);
-
-                // Create default queue
-                <CurrencyToAssetQueue<T>>::insert(asset_id.clone(), Vec::<T::AccountId>::new());
-                <AssetToCurrencyQueue<T>>::insert(asset_id.clone(), Vec::<T::AccountId>::new());
             }
         }
     }
@@ -265,57 +358,189 @@ pub mod pallet {
     #[pallet::storage]
     #[pallet::getter(fn pairs)]
     pub(super) type Pairs<T: Config> =
-        StorageMap<_, Twox64Concat, AssetIdOf<T>, PairOf<T>, OptionQuery>;
+        CountedStorageMap<_, Twox64Concat, AssetIdOf<T>, PairOf<T>, OptionQuery>;
 
-    /// The queue for currency to asset swaps.
-    /// Maps asset id to account.
+    /// The queued swap intents.
+    /// Maps (asset id, direction, queue index) to intent id
     #[pallet::storage]
-    #[pallet::unbounded]
-    #[pallet::getter(fn currency_to_asset_queue)]
-    pub(super) type CurrencyToAssetQueue<T: Config> =
-        StorageMap<_, Twox64Concat, AssetIdOf<T>, Vec<T::AccountId>, OptionQuery>;
+    pub(super) type Queues<T: Config> =
+        StorageNMap<
+            _,
+            (
+                NMapKey<Twox64Concat, AssetIdOf<T>>,
+                NMapKey<Twox64Concat, SwapDirection>,
+                NMapKey<Twox64Concat, u32>,
+            ),
+            IntentId,
+            OptionQuery
+        >;
 
-    /// The queue for asset to currency swaps.
-    /// Maps asset id to account
+    /// The head and tail of the queues.
+    /// Maps asset id to (maps direction to queue index)
     #[pallet::storage]
-    #[pallet::unbounded]
-    #[pallet::getter(fn asset_to_currency_queue)]
-    pub(super) type AssetToCurrencyQueue<T: Config> =
-        StorageMap<_, Twox64Concat, AssetIdOf<T>, Vec<T::AccountId>, OptionQuery>;
+    #[pallet::getter(fn queue_index)]
+    pub(super) type QueueIndices<T: Config> =
+        StorageDoubleMap<
+            _,
+            Twox64Concat,
+            AssetIdOf<T>,
+            Twox64Concat,
+            SwapDirection,
+            QueueIndex,
+            ValueQuery
+        >;
+
+    /// The inputs locked by the queued intents of a pair.
+    /// Maps asset id to (queued currency, queued asset)
+    #[pallet::storage]
+    #[pallet::getter(fn pending_inputs)]
+    pub(super) type PendingInputs<T: Config> =
+        StorageMap<_, Twox64Concat, AssetIdOf<T>, (BalanceOf<T>, AssetBalanceOf<T>), ValueQuery>;
 
+    /// The id of the next queued swap intent.
+    #[pallet::storage]
+    #[pallet::getter(fn next_intent_id)]
+    pub(super) type NextIntentId<T: Config> = StorageValue<_, IntentId, ValueQuery>;
+
+    /// The queued currency to asset swap intents.
+    /// Maps intent id to intent
+    #[pallet::storage]
+    #[pallet::getter(fn currency_intents)]
+    pub(super) type CurrencyToAssetIntents<T: Config> =
+        StorageMap<_, Twox64Concat, IntentId, CurrencyToAssetIntentOf<T>, OptionQuery>;
 
-    /// The cumulative for currency
-    /// Maps asset id to (maps account to amount)
+    /// The queued asset to currency swap intents.
+    /// Maps intent id to intent
     #[pallet::storage]
-    #[pallet::getter(fn currency_cumulative)]
-    pub(super) type CurrencyToAssetCumulative<T: Config> =
+    #[pallet::getter(fn asset_intents)]
+    pub(super) type AssetToCurrencyIntents<T: Config> =
+        StorageMap<_, Twox64Concat, IntentId, AssetToCurrencyIntentOf<T>, OptionQuery>;
+
+    /// The buy legs of queued asset to asset swaps.
+    /// Maps sell leg intent id to (bought asset id, minimum bought amount, deadline)
+    #[pallet::storage]
+    #[pallet::getter(fn next_legs)]
+    pub(super) type NextLegs<T: Config> = StorageMap<
+        _,
+        Twox64Concat,
+        IntentId,
+        (AssetIdOf<T>, AssetBalanceOf<T>, BlockNumberFor<T>),
+        OptionQuery,
+    >;
+
+    /// The block at which the first intent of the current batch was queued.
+    /// Maps asset id to block number
+    #[pallet::storage]
+    #[pallet::getter(fn epoch_start)]
+    pub(super) type EpochStart<T: Config> =
+        StorageMap<_, Twox64Concat, AssetIdOf<T>, BlockNumberFor<T>, OptionQuery>;
+
+    /// The last pair visited by the automatic settlement.
+    /// The next block continues from the pair after it.
+    #[pallet::storage]
+    #[pallet::getter(fn settlement_cursor)]
+    pub(super) type SettlementCursor<T: Config> = StorageValue<_, AssetIdOf<T>, OptionQuery>;
+
+    /// The hidden swap intents.
+    /// Maps account to (maps commitment hash to commitment)
+    #[pallet::storage]
+    #[pallet::getter(fn commitments)]
+    pub(super) type Commitments<T: Config> =
         StorageDoubleMap<
             _,
             Twox64Concat,
-            AssetIdOf<T>,
-            Twox64Concat,
             AccountIdOf<T>,
-            BalanceOf<T>,
+            Blake2_128Concat,
+            T::Hash,
+            CommitmentOf<T>,
             OptionQuery
         >;
 
-    /// The cumulative for asset
-    /// Maps asset id to (maps account to amount)
+    /// The settlement payouts waiting to be claimed.
+    /// Maps asset id to (maps intent id to claim)
     #[pallet::storage]
-    #[pallet::getter(fn asset_cumulative)]
-    pub(super) type AssetToCurrencyCumulative<T: Config> =
+    #[pallet::getter(fn claims)]
+    pub(super) type Claims<T: Config> =
         StorageDoubleMap<
             _,
             Twox64Concat,
             AssetIdOf<T>,
             Twox64Concat,
-            AccountIdOf<T>,
-            AssetBalanceOf<T>,
+            IntentId,
+            ClaimOf<T>,
             OptionQuery
         >;
 
+    /// The price of a pair before the first instant swap of the block.
+    /// Maps asset id to (block number, currency per asset)
+    #[pallet::storage]
+    #[pallet::getter(fn reference_prices)]
+    pub(super) type ReferencePrices<T: Config> =
+        StorageMap<_, Twox64Concat, AssetIdOf<T>, (BlockNumberFor<T>, FixedU128), OptionQuery>;
+
+    /// The price impact above which `swap` queues the orders of a pair.
+    /// Maps asset id to threshold
+    #[pallet::storage]
+    #[pallet::getter(fn impact_thresholds)]
+    pub(super) type ImpactThresholds<T: Config> =
+        StorageMap<_, Twox64Concat, AssetIdOf<T>, Perbill, OptionQuery>;
+
+    /// The liquidity tokens locked after being minted.
+    /// Maps liquidity token id to (maps provider to (locked amount, block the lock ends))
+    #[pallet::storage]
+    #[pallet::getter(fn liquidity_locks)]
+    pub(super) type LiquidityLocks<T: Config> =
+        StorageDoubleMap<
+            _,
+            Twox64Concat,
+            AssetIdOf<T>,
+            Blake2_128Concat,
+            T::AccountId,
+            (AssetBalanceOf<T>, BlockNumberFor<T>),
+            OptionQuery
+        >;
+
+    /// The registered solvers.
+    /// Maps account id to reserved bond
+    #[pallet::storage]
+    #[pallet::getter(fn solvers)]
+    pub(super) type Solvers<T: Config> =
+        StorageMap<_, Blake2_128Concat, T::AccountId, BalanceOf<T>, OptionQuery>;
+
+    /// The block the bidding on the mature batch of a pair ends.
+    /// Maps asset id to block number
+    #[pallet::storage]
+    #[pallet::getter(fn bidding_end)]
+    pub(super) type BiddingEnd<T: Config> =
+        StorageMap<_, Twox64Concat, AssetIdOf<T>, BlockNumberFor<T>, OptionQuery>;
+
+    /// The proposal giving the most surplus to the mature batch of a pair.
+    /// Maps asset id to bid
+    #[pallet::storage]
+    #[pallet::getter(fn best_bids)]
+    pub(super) type BestBids<T: Config> =
+        StorageMap<_, Twox64Concat, AssetIdOf<T>, BidOf<T>, OptionQuery>;
+
+    /// The intents of the mature batch of a pair the solvers bid on.
+    /// Maps asset id to (currency to asset intent ids, asset to currency intent ids)
+    #[pallet::storage]
+    #[pallet::getter(fn bidding_intents)]
+    pub(super) type BiddingIntents<T: Config> = StorageMap<
+        _,
+        Twox64Concat,
+        AssetIdOf<T>,
+        (BatchIntentIdsOf<T>, BatchIntentIdsOf<T>),
+        OptionQuery,
+    >;
+
+    /// The nonce the next signed intent of an account must carry.
+    /// Maps account id to nonce
+    #[pallet::storage]
+    #[pallet::getter(fn signed_intent_nonces)]
+    pub(super) type SignedIntentNonces<T: Config> =
+        StorageMap<_, Blake2_128Concat, T::AccountId, u64, ValueQuery>;
+
 	#[pallet::pallet]
-	#[pallet::without_storage_info]
 	pub struct Pallet<T>(_);
 
 	#[pallet::event]
@@ -355,23 +580,117 @@ pub mod pallet {
             BalanceOf<T>,
             AssetBalanceOf<T>,
         ),
-        /// Add swap currency for asset tx to queue (asset_id, recipient_id, amount_in)
+        /// Add swap currency for asset tx to queue (asset_id, intent_id, recipient_id, amount_in)
         AddedSwapCurrencyForAsset(
             AssetIdOf<T>,
+            IntentId,
             T::AccountId,
             BalanceOf<T>,
         ),
-        /// Add swap asset for currency tx to queue (asset_id, recipient_id, amount_in)
+        /// Add swap asset for currency tx to queue (asset_id, intent_id, recipient_id, amount_in)
         AddedSwapAssetForCurrency(
             AssetIdOf<T>,
+            IntentId,
+            T::AccountId,
+            AssetBalanceOf<T>,
+        ),
+        /// A swap intent was committed (account_id, commitment)
+        SwapCommitted(T::AccountId, T::Hash),
+        /// A committed swap intent was revealed and queued (account_id, commitment)
+        SwapRevealed(T::AccountId, T::Hash),
+        /// A commitment expired without reveal (account_id, commitment, forfeited_deposit)
+        CommitmentForfeited(T::AccountId, T::Hash, BalanceOf<T>),
+        /// Queued swap currency for asset was refunded (asset_id, intent_id, recipient_id, amount_in)
+        RefundedSwapCurrencyForAsset(
+            AssetIdOf<T>,
+            IntentId,
+            T::AccountId,
+            BalanceOf<T>,
+        ),
+        /// Queued swap asset for currency was refunded (asset_id, intent_id, recipient_id, amount_in)
+        RefundedSwapAssetForCurrency(
+            AssetIdOf<T>,
+            IntentId,
             T::AccountId,
             AssetBalanceOf<T>,
         ),
-        /// Settlement performed (asset_id, currency_out, asset_out)
+        /// Queued swap currency for asset was cancelled (asset_id, intent_id, recipient_id, amount_in)
+        CancelledSwapCurrencyForAsset(
+            AssetIdOf<T>,
+            IntentId,
+            T::AccountId,
+            BalanceOf<T>,
+        ),
+        /// Queued swap asset for currency was cancelled (asset_id, intent_id, recipient_id, amount_in)
+        CancelledSwapAssetForCurrency(
+            AssetIdOf<T>,
+            IntentId,
+            T::AccountId,
+            AssetBalanceOf<T>,
+        ),
+        /// Queued swap currency for asset passed its deadline and was refunded (asset_id, intent_id, recipient_id, amount_in)
+        ExpiredSwapCurrencyForAsset(
+            AssetIdOf<T>,
+            IntentId,
+            T::AccountId,
+            BalanceOf<T>,
+        ),
+        /// Queued swap asset for currency passed its deadline and was refunded (asset_id, intent_id, recipient_id, amount_in)
+        ExpiredSwapAssetForCurrency(
+            AssetIdOf<T>,
+            IntentId,
+            T::AccountId,
+            AssetBalanceOf<T>,
+        ),
+        /// The buy leg of an asset to asset swap could not be queued (bought_asset_id, recipient_id, currency_amount)
+        RefundedNextLeg(
+            AssetIdOf<T>,
+            T::AccountId,
+            BalanceOf<T>,
+        ),
+        /// The executor of a settlement was rewarded (executor_id, reward)
+        ExecutorRewarded(T::AccountId, BalanceOf<T>),
+        /// A settlement payout was claimed (asset_id, intent_id, recipient_id, currency_amount, asset_amount)
+        Claimed(
+            AssetIdOf<T>,
+            IntentId,
+            T::AccountId,
+            BalanceOf<T>,
+            AssetBalanceOf<T>,
+        ),
+        /// An account registered as a solver (solver_id, bond)
+        SolverRegistered(T::AccountId, BalanceOf<T>),
+        /// A solver unregistered and got its bond back (solver_id, bond)
+        SolverUnregistered(T::AccountId, BalanceOf<T>),
+        /// Solvers can bid on the mature batch of a pair (asset_id, bidding_end)
+        BiddingOpened(AssetIdOf<T>, BlockNumberFor<T>),
+        /// A solver proposed the best settlement so far (asset_id, solver_id, surplus)
+        SettlementProposed(AssetIdOf<T>, T::AccountId, BalanceOf<T>),
+        /// A batch was settled with the proposal of a solver (asset_id, solver_id, surplus)
+        SettledByProposal(AssetIdOf<T>, T::AccountId, BalanceOf<T>),
+        /// The solver of a settlement was paid (solver_id, fee)
+        SolverPaid(T::AccountId, BalanceOf<T>),
+        /// A signed intent was not queued (signer_id, nonce, error)
+        RejectedSignedIntent(T::AccountId, u64, DispatchError),
+        /// The impact threshold of a pair was changed, `None` restores the default (asset_id, threshold)
+        ImpactThresholdSet(AssetIdOf<T>, Option<Perbill>),
+        /// A swap was routed (account_id, asset_in, asset_out, amount_in, path)
+        Swapped(
+            T::AccountId,
+            SwapAsset<AssetIdOf<T>>,
+            SwapAsset<AssetIdOf<T>>,
+            BalanceOf<T>,
+            SwapPath,
+        ),
+        /// Settlement performed (asset_id, currency_out, asset_out, netted_currency, netted_asset, routed_currency, routed_asset)
         DistributeSettlement(
             AssetIdOf<T>,
             BalanceOf<T>,
             AssetBalanceOf<T>,
+            BalanceOf<T>,
+            AssetBalanceOf<T>,
+            BalanceOf<T>,
+            AssetBalanceOf<T>,
         ),
 	}
 
@@ -389,6 +708,12 @@ pub mod pallet {
         NotEnoughTokens,
         /// Specified account doesn't own enough liquidity in the pair
         ProviderLiquidityTooLow,
+        /// The liquidity was added too recently to be removed
+        LiquidityLocked,
+        /// The instant swap moves the price beyond the price band, use the anti-mev queue
+        PriceBandExceeded,
+        /// The swap has to trade the currency against an asset or two different assets
+        InvalidSwapRoute,
         /// No pair found for the given `asset_id`
         PairNotFound,
         /// Zero value provided for trade amount parameter
@@ -409,6 +734,10 @@ pub mod pallet {
         MaxTokensTooLow,
         /// Value provided for `min_liquidity` parameter is too high
         MinLiquidityTooHigh,
+        /// Value provided for `min_currency` parameter is too high
+        MinCurrencyTooHigh,
+        /// Value provided for `min_tokens` parameter is too high
+        MinTokensTooHigh,
         /// Zero value provided for `liquidity_amount` parameter
         LiquidityAmountIsZero,
         /// Zero value provided for `min_currency` parameter
@@ -433,6 +762,10 @@ pub mod pallet {
         DeadlinePassed,
         /// Queue too small
         QueueTooSmall,
+        /// Queue holds `MaxQueueLength` intents
+        QueueFull,
+        /// More pairs to settle than the given pair count
+        TooManyPairs,
         /// Currency overflow
         CurrencyOverflow,
         /// Asset overflow
@@ -441,8 +774,57 @@ pub mod pallet {
         CurrencyLeak,
         /// Asset leak
         AssetLeak,
+        /// The pair still has swap intents waiting for settlement
+        PendingSwapIntents,
+        /// The same swap intent was already committed
+        CommitmentAlreadyExists,
+        /// No commitment matches the revealed swap intent
+        CommitmentNotFound,
+        /// The reveal period of the commitment has not started yet
+        RevealTooEarly,
+        /// The reveal period of the commitment is over
+        RevealPeriodOver,
+        /// The reveal period of the commitment is not over yet
+        RevealPeriodNotOver,
+        /// The account has no queued swap intent in this direction
+        SwapIntentNotFound,
+        /// The intent has no settlement payout to claim
+        ClaimNotFound,
+        /// The queue is mature and locked for settlement
+        SettlementStarted,
+        /// The account is already a registered solver
+        AlreadySolver,
+        /// The account is not a registered solver
+        NotSolver,
+        /// Solvers can't bid on the batch of the pair
+        BiddingClosed,
+        /// Solvers are still bidding on the batch of the pair
+        BiddingOpen,
+        /// The proposal fills an intent twice or outside of the batch
+        InvalidFill,
+        /// The proposal fills an intent below its limit
+        FillBelowLimit,
+        /// The reserves of the proposal don't add up with its fills
+        ReservesMismatch,
+        /// The proposal decreases the constant product of the pair
+        InvariantBroken,
+        /// The proposal doesn't give more surplus than the best settlement so far
+        ProposalNotBetter,
+        /// The signature doesn't match the signed intent
+        BadSignature,
+        /// The signed intent doesn't carry the next nonce of its signer
+        BadNonce,
+        /// The proposal has more fills than the batch has intents
+        ProposalTooLarge,
 	}
 
+    #[pallet::hooks]
+    impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+        fn on_idle(now: BlockNumberFor<T>, remaining_weight: Weight) -> Weight {
+            Self::settle_mature_queues(now, remaining_weight)
+        }
+    }
+
    #[pallet::call]
 	impl<T: Config> Pallet<T> {
 		#[pallet::call_index(0)]
@@ -491,10 +873,6 @@ pub mod pallet {
                 caller,
             )?;
 
-            // create default queue
-            <CurrencyToAssetQueue<T>>::insert(asset_id.clone(), Vec::<T::AccountId>::new());
-            <AssetToCurrencyQueue<T>>::insert(asset_id.clone(), Vec::<T::AccountId>::new());
-
             Ok(())
 		}
 
@@ -534,13 +912,16 @@ pub mod pallet {
             ensure!(liquidity_minted >= min_liquidity, Error::<T>::MinLiquidityTooHigh);
 
             // perform the operation
+            let liquidity_token_id = pair.liquidity_token_id.clone();
             Self::inner_add_liquidity(
                 pair,
                 T::asset_to_currency(currency_amount),
                 token_amount,
                 liquidity_minted,
-                caller,
-            )
+                caller.clone(),
+            )?;
+            Self::lock_liquidity(&liquidity_token_id, &caller, liquidity_minted);
+            Ok(())
         }
 
         #[pallet::call_index(2)]
@@ -637,335 +1018,450 @@ pub mod pallet {
             )
         }
 
-        #[pallet::call_index(95)]
+        #[pallet::call_index(5)]
         #[pallet::weight(T::WeightInfo::default())]
-        pub fn add_swap_asset_for_asset(
+        #[transactional]
+        pub fn remove_liquidity(
             origin: OriginFor<T>,
-            sold_asset_id: AssetIdOf<T>,
-            bought_asset_id: AssetIdOf<T>,
-            amount_in: AssetBalanceOf<T>,
+            asset_id: AssetIdOf<T>,
+            liquidity_amount: AssetBalanceOf<T>,
+            min_currency: BalanceOf<T>,
+            min_tokens: AssetBalanceOf<T>,
             deadline: BlockNumberFor<T>,
         ) -> DispatchResult {
             // validate the input
             let caller = ensure_signed(origin)?;
             Self::check_deadline(&deadline)?;
-            ensure!(!amount_in.is_zero(), Error::<T>::TradeAmountIsZero);
-            Self::check_enough_tokens(&sold_asset_id, &caller, &amount_in)?;
-
-            let sold_pair = Self::get_pair(&sold_asset_id)?;
-            let sold_pair_asset_cumulative = Self::get_pair_asset_cumulative(&sold_asset_id, &caller);
-            let sold_pair_asset_queue = Self::get_pair_asset_queue(&sold_asset_id)?;
-
-            // pre compute to make sure the trade is possible
-            let currency_output = Self::cp_get_output_amount(
-                &T::asset_to_currency(amount_in),
-                &sold_pair.currency_reserve,
-                &T::asset_to_currency(sold_pair.token_reserve),
-            )?;
-
-            log::debug!(
-				target: LOG_TARGET,
-				"Current cumulative sold asset {:?} of {:?} is {:?}",
-				sold_asset_id,
-				caller,
-                sold_pair_asset_cumulative
-			);
-
-            let bought_pair = Self::get_pair(&bought_asset_id)?;
-            let bought_pair_currency_cumulative = Self::get_pair_currency_cumulative(&bought_asset_id, &caller);
-            let bought_pair_currency_queue = Self::get_pair_currency_queue(&bought_asset_id)?;
-
-            log::debug!(
-				target: LOG_TARGET,
-				"Current cumulative bought asset {:?} of {:?} is {:?}",
-				bought_asset_id,
-				caller,
-                bought_pair_currency_cumulative
-			);
-       
-            // pre compute to make sure the trade is possible
-            Self::cp_get_output_amount(
-                &currency_output,
-                &bought_pair.currency_reserve,
-                &T::asset_to_currency(bought_pair.token_reserve),
-            )?;
+            ensure!(liquidity_amount > Zero::zero(), Error::<T>::LiquidityAmountIsZero);
+            ensure!(min_currency > Zero::zero(), Error::<T>::MinCurrencyIsZero);
+            ensure!(min_tokens > Zero::zero(), Error::<T>::MinTokensIsZero);
+            let pair = Self::get_pair(&asset_id)?;
+            ensure!(
+                T::AssetRegistry::balance(pair.liquidity_token_id.clone(), &caller) >= liquidity_amount,
+                Error::<T>::ProviderLiquidityTooLow
+            );
+            ensure!(
+                T::AssetRegistry::balance(pair.liquidity_token_id.clone(), &caller)
+                    .saturating_sub(Self::locked_liquidity(&pair.liquidity_token_id, &caller))
+                    >= liquidity_amount,
+                Error::<T>::LiquidityLocked
+            );
 
-            // transfer to pallet account
-            let pallet_account = T::pallet_account();
-            T::Assets::transfer(
-                sold_asset_id.clone(),
-                &caller,
-                &pallet_account,
-                amount_in.clone(),
-                Preservation::Expendable,
-            )?;
-            
-            // add tx to queue
-            Self::add_asset_to_currency_tx(
-                sold_asset_id,
-                amount_in,
-                pallet_account,
-                sold_pair_asset_cumulative,
-                sold_pair_asset_queue,
+            // compute the pro-rata share of the reserves
+            let total_liquidity = T::AssetRegistry::total_issuance(pair.liquidity_token_id.clone());
+            debug_assert!(total_liquidity >= liquidity_amount);
+            let share = FixedU128::saturating_from_rational(liquidity_amount, total_liquidity);
+            let currency_amount = T::asset_to_currency(
+                share.saturating_mul_int(T::currency_to_asset(pair.currency_reserve))
+            );
+            let token_amount = share.saturating_mul_int(pair.token_reserve);
+            ensure!(currency_amount >= min_currency, Error::<T>::MinCurrencyTooHigh);
+            ensure!(token_amount >= min_tokens, Error::<T>::MinTokensTooHigh);
+
+            // queued intents are settled against the reserves of the pair, so they
+            // must keep what the pending inputs are worth at the spot price
+            if liquidity_amount == total_liquidity {
+                ensure!(!Self::has_pending_intents(&asset_id), Error::<T>::PendingSwapIntents);
+            }
+            let (pending_currency, pending_asset) = <PendingInputs<T>>::get(asset_id.clone());
+            let backed_tokens = Self::pro_rata(
+                T::currency_to_asset(pending_currency),
+                pair.token_reserve,
+                T::currency_to_asset(pair.currency_reserve),
             )?;
-            Self::add_currency_to_asset_tx(
-                bought_asset_id,
-                currency_output,
-                caller,
-                bought_pair_currency_cumulative,
-                bought_pair_currency_queue,
+            let backed_currency = Self::pro_rata(
+                T::asset_to_currency(pending_asset),
+                pair.currency_reserve,
+                T::asset_to_currency(pair.token_reserve),
             )?;
+            ensure!(
+                pair.token_reserve.saturating_sub(token_amount) >= backed_tokens
+                    && pair.currency_reserve.saturating_sub(currency_amount) >= backed_currency,
+                Error::<T>::PendingSwapIntents
+            );
 
+            // perform the operation
+            Self::inner_remove_liquidity(
+                pair,
+                currency_amount,
+                token_amount,
+                liquidity_amount,
+                caller,
+            )
+        }
 
+        #[pallet::call_index(82)]
+        #[pallet::weight(T::WeightInfo::default())]
+        pub fn set_impact_threshold(
+            origin: OriginFor<T>,
+            asset_id: AssetIdOf<T>,
+            threshold: Option<Perbill>,
+        ) -> DispatchResult {
+            ensure_root(origin)?;
+            Self::get_pair(&asset_id)?;
+            <ImpactThresholds<T>>::set(asset_id.clone(), threshold);
+            Self::deposit_event(Event::ImpactThresholdSet(asset_id, threshold));
             Ok(())
         }
 
-        #[pallet::call_index(96)]
+        /// Swap `amount` of `asset_in` for at least `limit` of `asset_out`, executed
+        /// on the curve when its price impact is within the threshold of the pairs
+        /// and queued into the anti-mev batch otherwise.
+        /// Asset amounts are converted with `CurrencyToAssetBalance`.
+        #[pallet::call_index(83)]
         #[pallet::weight(T::WeightInfo::default())]
-        pub fn add_swap_currency_for_asset(
+        pub fn swap(
             origin: OriginFor<T>,
-            asset_id: AssetIdOf<T>,
-            amount_in: BalanceOf<T>,
+            asset_in: SwapAsset<AssetIdOf<T>>,
+            asset_out: SwapAsset<AssetIdOf<T>>,
+            amount: BalanceOf<T>,
+            limit: BalanceOf<T>,
             deadline: BlockNumberFor<T>,
         ) -> DispatchResult {
             // validate the input
             let caller = ensure_signed(origin)?;
             Self::check_deadline(&deadline)?;
-            ensure!(!amount_in.is_zero(), Error::<T>::TradeAmountIsZero);
-            Self::check_enough_currency(&caller, &amount_in)?;
-
-            let pair = Self::get_pair(&asset_id)?;
-            let pair_currency_cumulative = Self::get_pair_currency_cumulative(&asset_id, &caller);
-            let pair_currency_queue = Self::get_pair_currency_queue(&asset_id)?;
-
-
-            log::debug!(
-				target: LOG_TARGET,
-				"Current cumulative asset {:?} of {:?} is {:?}",
-				asset_id,
-				caller,
-                pair_currency_cumulative
-			);
-
-            // pre compute to make sure the trade is possible
-            Self::cp_get_output_amount(
-                &amount_in,
-                &pair.currency_reserve,
-                &T::asset_to_currency(pair.token_reserve),
+            ensure!(!amount.is_zero(), Error::<T>::TradeAmountIsZero);
+            let path = Self::do_swap(
+                caller.clone(),
+                asset_in.clone(),
+                asset_out.clone(),
+                amount,
+                limit,
+                deadline,
             )?;
+            Self::deposit_event(Event::Swapped(caller, asset_in, asset_out, amount, path));
+            Ok(())
+        }
 
-            // transfer to pallet account
-            let pallet_account = T::pallet_account();
-            <T as pallet::Config>::Currency::transfer(
-                &caller,
-                &pallet_account,
-                amount_in.clone(),
-                ExistenceRequirement::KeepAlive,
-            )?;
+        #[pallet::call_index(88)]
+        #[pallet::weight(T::WeightInfo::default())]
+        pub fn claim(
+            origin: OriginFor<T>,
+            asset_id: AssetIdOf<T>,
+            intent_id: IntentId,
+        ) -> DispatchResult {
+            // anyone can pay out a claim to its owner
+            ensure_signed(origin)?;
+            Self::do_claim(&asset_id, intent_id)
+        }
 
-            // add tx to queue
-            Self::add_currency_to_asset_tx(
-                asset_id,
-                amount_in,
-                caller,
-                pair_currency_cumulative,
-                pair_currency_queue,
-            )?;
+        #[pallet::call_index(84)]
+        #[pallet::weight(T::WeightInfo::submit_settlement(
+            T::MinQueueAmount::get().saturating_mul(2),
+            proposal.buy_fills.len().saturating_add(proposal.sell_fills.len()) as u32,
+        ))]
+        pub fn submit_settlement(
+            origin: OriginFor<T>,
+            asset_id: AssetIdOf<T>,
+            proposal: SettlementProposalOf<T>,
+        ) -> DispatchResult {
+            let solver = ensure_signed(origin)?;
+            ensure!(<Solvers<T>>::contains_key(&solver), Error::<T>::NotSolver);
+            Self::do_submit_settlement(solver, &asset_id, proposal)
+        }
 
+        #[pallet::call_index(85)]
+        #[pallet::weight(T::WeightInfo::default())]
+        pub fn unregister_solver(origin: OriginFor<T>) -> DispatchResult {
+            let solver = ensure_signed(origin)?;
+            let bond = <Solvers<T>>::take(&solver).ok_or(Error::<T>::NotSolver)?;
+            <T as pallet::Config>::Currency::unreserve(&solver, bond);
+            Self::deposit_event(Event::SolverUnregistered(solver, bond));
+            Ok(())
+        }
 
+        #[pallet::call_index(86)]
+        #[pallet::weight(T::WeightInfo::default())]
+        pub fn register_solver(origin: OriginFor<T>) -> DispatchResult {
+            let solver = ensure_signed(origin)?;
+            ensure!(!<Solvers<T>>::contains_key(&solver), Error::<T>::AlreadySolver);
+            let bond = T::SolverBond::get();
+            <T as pallet::Config>::Currency::reserve(&solver, bond)?;
+            <Solvers<T>>::insert(&solver, bond);
+            Self::deposit_event(Event::SolverRegistered(solver, bond));
             Ok(())
         }
 
-        #[pallet::call_index(97)]
+        #[pallet::call_index(87)]
+        #[pallet::weight(T::WeightInfo::submit_signed_intents(intents.len() as u32))]
+        pub fn submit_signed_intents(
+            origin: OriginFor<T>,
+            intents: BoundedVec<(SignedIntentOf<T>, T::OffchainSignature), T::MaxSignedIntents>,
+        ) -> DispatchResult {
+            // anyone can relay the intents, each signer pays its own input
+            ensure_signed(origin)?;
+            for (intent, signature) in intents {
+                let (signer, nonce) = (intent.signer.clone(), intent.nonce);
+                if let Err(e) = frame_support::storage::with_storage_layer(|| {
+                    Self::do_submit_signed_intent(intent, &signature)
+                }) {
+                    Self::deposit_event(Event::RejectedSignedIntent(signer, nonce, e));
+                }
+            }
+            Ok(())
+        }
+
+        #[pallet::call_index(89)]
         #[pallet::weight(T::WeightInfo::default())]
-        pub fn add_swap_asset_for_currency(
+        pub fn add_swap_currency_for_exact_asset(
             origin: OriginFor<T>,
             asset_id: AssetIdOf<T>,
-            amount_in: AssetBalanceOf<T>,
+            output_amount: AssetBalanceOf<T>,
+            max_input: BalanceOf<T>,
             deadline: BlockNumberFor<T>,
         ) -> DispatchResult {
             // validate the input
             let caller = ensure_signed(origin)?;
             Self::check_deadline(&deadline)?;
-            ensure!(!amount_in.is_zero(), Error::<T>::TradeAmountIsZero);
-            Self::check_enough_tokens(&asset_id, &caller, &amount_in)?;
+            Self::queue_swap_currency_for_asset(
+                caller,
+                asset_id,
+                AntiMevSwap::BasedOutput { max_input, output_amount },
+                deadline,
+            )
+        }
 
-            let pair = Self::get_pair(&asset_id)?;
-            let pair_asset_cumulative = Self::get_pair_asset_cumulative(&asset_id, &caller);
-            let pair_asset_queue = Self::get_pair_asset_queue(&asset_id)?;
-
-            log::debug!(
-				target: LOG_TARGET,
-				"Current cumulative asset {:?} of {:?} is {:?}",
-				asset_id,
-				caller,
-                pair_asset_cumulative
-			);
-
-            // pre compute to make sure the trade is possible
-            Self::cp_get_output_amount(
-                &T::asset_to_currency(amount_in),
-                &T::asset_to_currency(pair.token_reserve),
-                &pair.currency_reserve,
-            )?;
+        #[pallet::call_index(90)]
+        #[pallet::weight(T::WeightInfo::default())]
+        pub fn add_swap_asset_for_exact_currency(
+            origin: OriginFor<T>,
+            asset_id: AssetIdOf<T>,
+            output_amount: BalanceOf<T>,
+            max_input: AssetBalanceOf<T>,
+            deadline: BlockNumberFor<T>,
+        ) -> DispatchResult {
+            // validate the input
+            let caller = ensure_signed(origin)?;
+            Self::check_deadline(&deadline)?;
+            Self::queue_swap_asset_for_currency(
+                caller,
+                asset_id,
+                AntiMevSwap::BasedOutput { max_input, output_amount },
+                deadline,
+            )
+        }
 
-            // transfer to pallet account
-            let pallet_account = T::pallet_account();
-            T::Assets::transfer(
-                asset_id.clone(),
-                &caller,
-                &pallet_account,
-                amount_in.clone(),
-                Preservation::Preserve,
-            )?;
+        #[pallet::call_index(91)]
+        #[pallet::weight(T::WeightInfo::cancel_swap_intent(T::MaxQueueLength::get()))]
+        #[transactional]
+        pub fn cancel_swap_intent(
+            origin: OriginFor<T>,
+            asset_id: AssetIdOf<T>,
+            direction: SwapDirection,
+        ) -> DispatchResult {
+            let caller = ensure_signed(origin)?;
+            ensure!(
+                !Self::is_queue_mature(&asset_id, <frame_system::Pallet<T>>::block_number())?,
+                Error::<T>::SettlementStarted
+            );
+            Self::do_cancel_swap_intent(&asset_id, &caller, direction)
+        }
 
-            // add tx to queue
-            Self::add_asset_to_currency_tx(
-                asset_id,
-                amount_in,
-                caller,
-                pair_asset_cumulative,
-                pair_asset_queue,
-            )?;
+        #[pallet::call_index(92)]
+        #[pallet::weight(T::WeightInfo::default())]
+        pub fn commit_swap_intent(
+            origin: OriginFor<T>,
+            commitment: T::Hash,
+        ) -> DispatchResult {
+            let caller = ensure_signed(origin)?;
+            ensure!(
+                !<Commitments<T>>::contains_key(&caller, commitment),
+                Error::<T>::CommitmentAlreadyExists
+            );
 
+            let deposit = T::CommitDeposit::get();
+            <T as pallet::Config>::Currency::reserve(&caller, deposit)?;
+            <Commitments<T>>::insert(&caller, commitment, Commitment {
+                deposit,
+                committed_at: <frame_system::Pallet<T>>::block_number(),
+            });
 
+            Self::deposit_event(Event::SwapCommitted(caller, commitment));
             Ok(())
         }
 
-        #[pallet::call_index(99)]
-        #[pallet::weight(T::WeightInfo::default())]
-        pub fn settle_and_distribute(
+        #[pallet::call_index(93)]
+        #[pallet::weight(T::WeightInfo::reveal_swap_intent())]
+        #[transactional]
+        pub fn reveal_swap_intent(
             origin: OriginFor<T>,
             asset_id: AssetIdOf<T>,
+            direction: SwapDirection,
+            amount_in: BalanceOf<T>,
+            min_output: BalanceOf<T>,
+            deadline: BlockNumberFor<T>,
+            salt: [u8; 32],
         ) -> DispatchResult {
-            let _executor = ensure_signed(origin)?;
-            let currency_queue = Self::get_pair_currency_queue(&asset_id)?;
-            let asset_queue = Self::get_pair_asset_queue(&asset_id)?;
-            let execute_items = T::MinQueueAmount::get();
-            ensure!(
-                currency_queue.len() >= execute_items as usize,
-                Error::<T>::QueueTooSmall
-            );
+            let caller = ensure_signed(origin)?;
+            let commitment =
+                Self::commitment_of(&asset_id, direction, amount_in, min_output, deadline, &salt);
+            let Commitment { deposit, committed_at } = <Commitments<T>>::take(&caller, commitment)
+                .ok_or(Error::<T>::CommitmentNotFound)?;
+
+            // the intent can only be revealed inside its reveal window
+            let now = <frame_system::Pallet<T>>::block_number();
+            let reveal_start = committed_at.saturating_add(T::RevealDelay::get());
+            ensure!(now >= reveal_start, Error::<T>::RevealTooEarly);
             ensure!(
-                asset_queue.len() >= execute_items as usize,
-                Error::<T>::QueueTooSmall
-            );
-            
-            // Sum of all currency and asset in queue
-            let total_cumulative_currency: BalanceOf<T> = 
-                Self::calculate_cumulative_currency(&asset_id, &currency_queue, execute_items.clone() as usize);
-            let total_cumulative_asset: BalanceOf<T> = T::asset_to_currency(
-                Self::calculate_cumulative_asset(&asset_id, &asset_queue, execute_items.clone() as usize)
+                now < reveal_start.saturating_add(T::RevealPeriod::get()),
+                Error::<T>::RevealPeriodOver
             );
-            
-            let modified_cumulative_currency =
-                total_cumulative_currency.clone() * T::ProviderFeeNumerator::get().into() / T::ProviderFeeDenominator::get().into();
-            let modified_cumulative_asset = 
-                total_cumulative_asset.clone() * T::ProviderFeeNumerator::get().into() / T::ProviderFeeDenominator::get().into();
+            <T as pallet::Config>::Currency::unreserve(&caller, deposit);
+            Self::check_deadline(&deadline)?;
 
-            // Temporary reserves to save gas
-            let pair = Self::get_pair(&asset_id)?;
-            let mut temporary_currency_reserve = pair.currency_reserve;
-            let mut temporary_asset_reserve = T::asset_to_currency(pair.token_reserve);
-            let constant_product = temporary_currency_reserve.clone() * temporary_asset_reserve.clone();
+            // add tx to queue
+            match direction {
+                SwapDirection::CurrencyToAsset => Self::queue_swap_currency_for_asset(
+                    caller.clone(),
+                    asset_id,
+                    AntiMevSwap::BasedInput {
+                        input_amount: amount_in,
+                        min_output: T::currency_to_asset(min_output),
+                    },
+                    deadline,
+                )?,
+                SwapDirection::AssetToCurrency => Self::queue_swap_asset_for_currency(
+                    caller.clone(),
+                    asset_id,
+                    AntiMevSwap::BasedInput {
+                        input_amount: T::currency_to_asset(amount_in),
+                        min_output,
+                    },
+                    deadline,
+                )?,
+            };
 
+            Self::deposit_event(Event::SwapRevealed(caller, commitment));
+            Ok(())
+        }
 
-            let fragment = T::Fragment::get();
-            for _i in 1..=fragment {
-                // Base currency increase and quote currency decrease
-                temporary_currency_reserve += modified_cumulative_currency / fragment.into();
-                temporary_asset_reserve = constant_product / temporary_currency_reserve;
+        #[pallet::call_index(94)]
+        #[pallet::weight(T::WeightInfo::default())]
+        pub fn clear_expired_commitment(
+            origin: OriginFor<T>,
+            account_id: AccountIdOf<T>,
+            commitment: T::Hash,
+        ) -> DispatchResult {
+            ensure_signed(origin)?;
+            let Commitment { deposit, committed_at } = <Commitments<T>>::get(&account_id, commitment)
+                .ok_or(Error::<T>::CommitmentNotFound)?;
+            let reveal_end = committed_at
+                .saturating_add(T::RevealDelay::get())
+                .saturating_add(T::RevealPeriod::get());
+            ensure!(
+                <frame_system::Pallet<T>>::block_number() >= reveal_end,
+                Error::<T>::RevealPeriodNotOver
+            );
+            <Commitments<T>>::remove(&account_id, commitment);
 
-                // Quote currency increase and base currency decrease
-                temporary_asset_reserve += modified_cumulative_asset / fragment.into();
-                temporary_currency_reserve = constant_product / temporary_asset_reserve;
+            // the forfeited part is burned, the rest goes back to the owner
+            let forfeit = T::CommitForfeit::get().mul_floor(deposit);
+            let (_, not_slashed) = <T as pallet::Config>::Currency::slash_reserved(&account_id, forfeit);
+            let forfeited = forfeit.saturating_sub(not_slashed);
+            <T as pallet::Config>::Currency::unreserve(&account_id, deposit.saturating_sub(forfeit));
 
-                // NOTE: This algorithm is only asymptotically unbiased,
-                // because the increase of base currency always goes first.
-                // If we want to make it completely unbiased, we should
-                // simulate in another direction and calculate the mean value.
-            }
+            Self::deposit_event(Event::CommitmentForfeited(account_id, commitment, forfeited));
+            Ok(())
+        }
 
-            // Calculate the output
-            if temporary_currency_reserve > pair.currency_reserve + total_cumulative_currency {
-                log::error!(
-                    target: LOG_TARGET,
-                    "Currency overflow: {:?} > {:?} + {:?}",
-                    temporary_currency_reserve,
-                    pair.currency_reserve,
-                    total_cumulative_currency
-                );
-                return Err(Error::<T>::CurrencyOverflow.into());
-            }
-            if temporary_asset_reserve > T::asset_to_currency(pair.token_reserve) + total_cumulative_asset {
-                log::error!(
-                    target: LOG_TARGET,
-                    "Asset overflow: {:?} > {:?} + {:?}",
-                    temporary_asset_reserve,
-                    pair.token_reserve,
-                    total_cumulative_asset
-                );
-                return Err(Error::<T>::AssetOverflow.into());
-            }
+        #[pallet::call_index(95)]
+        #[pallet::weight(T::WeightInfo::default())]
+        pub fn add_swap_asset_for_asset(
+            origin: OriginFor<T>,
+            sold_asset_id: AssetIdOf<T>,
+            bought_asset_id: AssetIdOf<T>,
+            amount_in: AssetBalanceOf<T>,
+            min_output: AssetBalanceOf<T>,
+            deadline: BlockNumberFor<T>,
+        ) -> DispatchResult {
+            // validate the input
+            let caller = ensure_signed(origin)?;
+            Self::check_deadline(&deadline)?;
+            Self::queue_swap_asset_for_asset(
+                caller,
+                sold_asset_id,
+                bought_asset_id,
+                amount_in,
+                min_output,
+                deadline,
+            )
+        }
 
-            let currency_out = 
-                pair.currency_reserve + total_cumulative_currency - temporary_currency_reserve;
-            let asset_out = 
-                T::asset_to_currency(pair.token_reserve) + total_cumulative_asset - temporary_asset_reserve;
-
-            // Distribute
-            for i in 0..execute_items.clone() as usize {
-                Self::do_anti_mev_swap_currency_for_asset(
-                    &asset_id,
-                    &currency_queue[i],
-                    &asset_out,
-                    &total_cumulative_currency
-                )?;
-            }
+        #[pallet::call_index(96)]
+        #[pallet::weight(T::WeightInfo::default())]
+        pub fn add_swap_currency_for_asset(
+            origin: OriginFor<T>,
+            asset_id: AssetIdOf<T>,
+            amount_in: BalanceOf<T>,
+            min_output: AssetBalanceOf<T>,
+            deadline: BlockNumberFor<T>,
+        ) -> DispatchResult {
+            // validate the input
+            let caller = ensure_signed(origin)?;
+            Self::check_deadline(&deadline)?;
+            Self::queue_swap_currency_for_asset(
+                caller,
+                asset_id,
+                AntiMevSwap::BasedInput { input_amount: amount_in, min_output },
+                deadline,
+            )
+        }
 
-            for i in 0..execute_items.clone() as usize {
-                Self::do_anti_mev_swap_asset_for_currency(
-                    &asset_id,
-                    &asset_queue[i],
-                    &T::currency_to_asset(currency_out),
-                    &T::currency_to_asset(total_cumulative_asset),
-                )?;
-            }
+        #[pallet::call_index(97)]
+        #[pallet::weight(T::WeightInfo::default())]
+        pub fn add_swap_asset_for_currency(
+            origin: OriginFor<T>,
+            asset_id: AssetIdOf<T>,
+            amount_in: AssetBalanceOf<T>,
+            min_output: BalanceOf<T>,
+            deadline: BlockNumberFor<T>,
+        ) -> DispatchResult {
+            // validate the input
+            let caller = ensure_signed(origin)?;
+            Self::check_deadline(&deadline)?;
+            Self::queue_swap_asset_for_currency(
+                caller,
+                asset_id,
+                AntiMevSwap::BasedInput { input_amount: amount_in, min_output },
+                deadline,
+            )
+        }
 
-            // Update the reserves
-            let pallet_account = T::pallet_account();
-            ensure!(
-                <T as Config>::Currency::free_balance(&pallet_account) >= temporary_currency_reserve,
-                Error::<T>::CurrencyLeak
-            );
+        #[pallet::call_index(98)]
+        #[pallet::weight(Pallet::<T>::settle_all_weight(*pair_count, <Pairs<T>>::count()))]
+        #[allow(clippy::useless_conversion)]
+        pub fn settle_all(
+            origin: OriginFor<T>,
+            pair_count: u32,
+        ) -> DispatchResultWithPostInfo {
+            let executor = ensure_signed(origin)?;
+            let (settled, visited, fee) = Self::do_settle_all(pair_count)?;
+            Self::reward_executor(&executor, fee);
+
+            // settling on time is free
+            let actual_weight = Pallet::<T>::settle_all_weight(settled, visited);
+            Ok((Some(actual_weight), Pays::No).into())
+        }
+
+        #[pallet::call_index(99)]
+        #[pallet::weight(Pallet::<T>::settle_weight())]
+        #[allow(clippy::useless_conversion)]
+        pub fn settle_and_distribute(
+            origin: OriginFor<T>,
+            asset_id: AssetIdOf<T>,
+        ) -> DispatchResultWithPostInfo {
+            let executor = ensure_signed(origin)?;
             ensure!(
-                T::Assets::balance(asset_id.clone(), &pallet_account) >= T::currency_to_asset(temporary_asset_reserve),
-                Error::<T>::AssetLeak
+                Self::is_queue_mature(&asset_id, <frame_system::Pallet<T>>::block_number())?,
+                Error::<T>::QueueTooSmall
             );
-
-            // Reset for the next settlement period
-            for i in 0..(execute_items.clone() as usize) {
-                <CurrencyToAssetCumulative<T>>::remove(asset_id.clone(), currency_queue[i].clone());
+            if let Some(fee) = Self::do_settle_pair(&asset_id)? {
+                Self::reward_executor(&executor, fee);
             }
-            for i in 0..(execute_items.clone() as usize) {
-                <AssetToCurrencyCumulative<T>>::remove(asset_id.clone(), asset_queue[i].clone());
-            }
-            <CurrencyToAssetQueue<T>>::insert(asset_id.clone(), Vec::<T::AccountId>::new());
-            <AssetToCurrencyQueue<T>>::insert(asset_id.clone(), Vec::<T::AccountId>::new());
-
-            // TODO: distribute the rewards
-            // For example:
-            // - transfer native currency
-            // - mint/transfer reward asset
 
-            // emit SettlementPerformed(BaseCurrencyOut, QuoteCurrencyOut, block.timestamp);
-            Self::deposit_event(Event::DistributeSettlement(
-                asset_id,
-                currency_out,
-                T::currency_to_asset(asset_out),
-            ));
-
-            Ok(())
+            // settling on time is free
+            Ok(Pays::No.into())
         }
 	}
 
@@ -1021,6 +1517,119 @@ pub mod pallet {
             Ok(())
         }
 
+        /// Perform currency and asset transfers, burn liquidity token,
+        /// update pair balances, emit event
+        #[transactional]
+        fn inner_remove_liquidity(
+            mut pair: PairOf<T>,
+            currency_amount: BalanceOf<T>,
+            token_amount: AssetBalanceOf<T>,
+            liquidity_amount: AssetBalanceOf<T>,
+            provider: AccountIdOf<T>,
+        ) -> DispatchResult {
+            // burn liquidity tokens from the provider
+            let asset_id = pair.asset_id.clone();
+            let pallet_account = T::pallet_account();
+            T::AssetRegistry::burn_from(
+                pair.liquidity_token_id.clone(),
+                &provider,
+                liquidity_amount,
+                Preservation::Expendable,
+                Precision::Exact,
+                Fortitude::Polite,
+            )?;
+            // the lock of the provider is over
+            if Self::locked_liquidity(&pair.liquidity_token_id, &provider).is_zero() {
+                <LiquidityLocks<T>>::remove(pair.liquidity_token_id.clone(), &provider);
+            }
+            // transfer currency and asset tokens from liquidity
+            <T as pallet::Config>::Currency::transfer(
+                &pallet_account,
+                &provider,
+                currency_amount,
+                ExistenceRequirement::AllowDeath,
+            )?;
+            T::Assets::transfer(
+                asset_id.clone(),
+                &pallet_account,
+                &provider,
+                token_amount,
+                Preservation::Expendable,
+            )?;
+
+            // reduce liquidity reserves
+            pair.currency_reserve.saturating_reduce(currency_amount);
+            pair.token_reserve.saturating_reduce(token_amount);
+
+            // funds of queued intents must still be held by the pallet account
+            ensure!(
+                <T as Config>::Currency::free_balance(&pallet_account)
+                    >= pair.currency_reserve.saturating_add(Self::pending_currency(&asset_id)),
+                Error::<T>::CurrencyLeak
+            );
+            ensure!(
+                T::Assets::balance(asset_id.clone(), &pallet_account)
+                    >= pair.token_reserve.saturating_add(Self::pending_asset(&asset_id)),
+                Error::<T>::AssetLeak
+            );
+            if T::AssetRegistry::total_issuance(pair.liquidity_token_id.clone()).is_zero() {
+                // the last provider left, the liquidity token stays registered
+                // so the pair can only be created again with a new one
+                <Pairs<T>>::remove(asset_id.clone());
+                <PendingInputs<T>>::remove(asset_id.clone());
+                <EpochStart<T>>::remove(asset_id.clone());
+                <ReferencePrices<T>>::remove(asset_id.clone());
+                <ImpactThresholds<T>>::remove(asset_id.clone());
+                <BiddingEnd<T>>::remove(asset_id.clone());
+                <BestBids<T>>::remove(asset_id.clone());
+                <BiddingIntents<T>>::remove(asset_id.clone());
+            } else {
+                <Pairs<T>>::insert(asset_id.clone(), pair);
+            }
+
+            // emit event
+            Self::deposit_event(Event::LiquidityRemoved(
+                provider,
+                asset_id,
+                currency_amount,
+                token_amount,
+                liquidity_amount,
+            ));
+            Ok(())
+        }
+
+        /// Lock liquidity tokens minted to a provider for `LiquidityHoldingPeriod`
+        /// blocks, so liquidity can't be added just for the settlement of a batch.
+        /// A new lock of the provider extends its previous one.
+        pub(crate) fn lock_liquidity(
+            liquidity_token_id: &AssetIdOf<T>,
+            provider: &T::AccountId,
+            liquidity_minted: AssetBalanceOf<T>,
+        ) {
+            let holding_period = T::LiquidityHoldingPeriod::get();
+            if holding_period.is_zero() {
+                return;
+            }
+            let locked = Self::locked_liquidity(liquidity_token_id, provider);
+            let unlock_at = <frame_system::Pallet<T>>::block_number().saturating_add(holding_period);
+            <LiquidityLocks<T>>::insert(
+                liquidity_token_id.clone(),
+                provider,
+                (locked.saturating_add(liquidity_minted), unlock_at),
+            );
+        }
+
+        /// The liquidity tokens of a provider that can't be removed or transferred yet.
+        pub fn locked_liquidity(
+            liquidity_token_id: &AssetIdOf<T>,
+            provider: &T::AccountId,
+        ) -> AssetBalanceOf<T> {
+            match <LiquidityLocks<T>>::get(liquidity_token_id.clone(), provider) {
+                Some((locked, unlock_at)) if <frame_system::Pallet<T>>::block_number() < unlock_at => locked,
+                _ => Zero::zero(),
+            }
+        }
+
         pub(crate) fn get_pair(asset_id: &AssetIdOf<T>) -> Result<PairOf<T>, Error<T>> {
             <Pairs<T>>::get(asset_id.clone()).ok_or(Error::<T>::PairNotFound)
         }
@@ -1054,33 +1663,38 @@ pub mod pallet {
             }
         }
 
+        /// The total input of the intents, exact output intents counted with
+        /// their sized input.
         pub(crate) fn calculate_cumulative_currency(
-            asset_id: &AssetIdOf<T>,
-            queue: &Vec<T::AccountId>,
-            max_usize: usize
-        ) -> BalanceOf<T> {
-            let mut total_cumulative: BalanceOf<T> = Zero::zero();
-            queue.iter()
-                .take(max_usize)
-                .for_each(|account| {
-                    total_cumulative += Self::get_pair_currency_cumulative(&asset_id, &account);
-                });
-            total_cumulative
+            intents: &[(IntentId, CurrencyToAssetIntentOf<T>)],
+            sized_inputs: &BTreeMap<IntentId, BalanceOf<T>>,
+        ) -> Result<BalanceOf<T>, ArithmeticError> {
+            intents.iter()
+                .try_fold(Zero::zero(), |total_cumulative: BalanceOf<T>, (intent_id, intent)| {
+                    total_cumulative
+                        .checked_add(&sized_inputs
+                            .get(intent_id)
+                            .copied()
+                            .unwrap_or_else(|| intent.swap.locked_input()))
+                        .ok_or(ArithmeticError::Overflow)
+                })
         }
 
+        /// The total input of the intents, exact output intents counted with
+        /// their sized input.
         pub(crate) fn calculate_cumulative_asset(
-            asset_id: &AssetIdOf<T>,
-            queue: &Vec<T::AccountId>,
-            max_usize: usize
-        ) -> AssetBalanceOf<T> {
-            let mut total_cumulative: AssetBalanceOf<T> = Zero::zero();
-            // just take max_usize elements
-            queue.iter()
-                .take(max_usize)
-                .for_each(|account| {
-                    total_cumulative += Self::get_pair_asset_cumulative(&asset_id, &account);
-                });
-            total_cumulative
+            intents: &[(IntentId, AssetToCurrencyIntentOf<T>)],
+            sized_inputs: &BTreeMap<IntentId, AssetBalanceOf<T>>,
+        ) -> Result<AssetBalanceOf<T>, ArithmeticError> {
+            intents.iter()
+                .try_fold(Zero::zero(), |total_cumulative: AssetBalanceOf<T>, (intent_id, intent)| {
+                    total_cumulative
+                        .checked_add(&sized_inputs
+                            .get(intent_id)
+                            .copied()
+                            .unwrap_or_else(|| intent.swap.locked_input()))
+                        .ok_or(ArithmeticError::Overflow)
+                })
         }
 
     }
@@ -1,16 +1,20 @@
 use crate as pallet_anti_mev_amm;
 use frame_support::{
     traits::{
-        AsEnsureOriginWithArg, ConstU128, ConstU16, ConstU32, ConstU64,
+        AsEnsureOriginWithArg, ConstU128, ConstU16, ConstU32, ConstU64, Randomness,
     },
     construct_runtime, parameter_types, derive_impl,
     PalletId,
 };
 use sp_runtime::{
-    traits::{BlakeTwo256, Identity, IdentityLookup},
-    BuildStorage,
+    traits::{BlakeTwo256, Hash as HashT, Identity, IdentityLookup},
+    testing::{TestSignature, UintAuthorityId},
+    BuildStorage, Perbill,
 };
 use frame_system::{EnsureRoot, EnsureSigned};
+use pallet_assets::FrozenBalance;
+#[cfg(feature = "runtime-benchmarks")]
+use frame_support::traits::fungibles::Create;
 use sp_core::H256;
 
 type Balance = u128;
@@ -89,7 +93,7 @@ impl pallet_assets::Config for TestRuntime {
     type MetadataDepositPerByte = ConstU128<1>;
     type ApprovalDeposit = ConstU128<1>;
     type StringLimit = ConstU32<50>;
-    type Freezer = ();
+    type Freezer = LiquidityFreezer;
     type Extra = ();
     type WeightInfo = ();
     type RemoveItemsLimit = ConstU32<5>;
@@ -99,6 +103,38 @@ impl pallet_assets::Config for TestRuntime {
 
 parameter_types! {
     pub const AniMevAmmPalletId: PalletId = PalletId(*b"anti_mev");
+    pub const AntiMevRewardPotId: PalletId = PalletId(*b"mevrward");
+    pub const CommitForfeit: Perbill = Perbill::from_percent(50);
+    pub static RandomSeed: H256 = H256::zero();
+    pub static RandomKnownSince: Option<u64> = None;
+    pub static BiddingPeriod: u64 = 0;
+    pub static PriceBand: Option<Perbill> = None;
+    pub const DefaultImpactThreshold: Perbill = Perbill::from_percent(1);
+}
+
+/// Keeps the locked liquidity tokens from being transferred.
+pub struct LiquidityFreezer;
+
+impl FrozenBalance<AssetId, u64, Balance> for LiquidityFreezer {
+    fn frozen_balance(asset: AssetId, who: &u64) -> Option<Balance> {
+        Some(AntiMevAmm::locked_liquidity(&asset, who)).filter(|locked| *locked > 0)
+    }
+
+    fn died(_asset: AssetId, _who: &u64) {}
+}
+
+/// Deterministic randomness, tests pick the outcome with `RandomSeed::set`.
+/// It is known since `RandomKnownSince`, or the next block when unset, so
+/// intents queued up to the current block are settled with it.
+pub struct TestRandomness;
+
+impl Randomness<H256, u64> for TestRandomness {
+    fn random(subject: &[u8]) -> (H256, u64) {
+        (
+            BlakeTwo256::hash_of(&(RandomSeed::get(), subject)),
+            RandomKnownSince::get().unwrap_or_else(|| System::block_number() + 1),
+        )
+    }
 }
 
 impl pallet_anti_mev_amm::Config for TestRuntime {
@@ -120,6 +156,45 @@ impl pallet_anti_mev_amm::Config for TestRuntime {
     type Fragment = ConstU32<10>;
     // Max queue amount is 2, there can be at most 4 transactions
     type MinQueueAmount = ConstU32<2>;
+    type MaxQueueLength = ConstU32<4>;
+    // A queue that is not full is settled after 10 blocks
+    type EpochLength = ConstU64<10>;
+    type CommitDeposit = ConstU128<1_000>;
+    // A commitment can be revealed from the next block, during 5 blocks
+    type RevealDelay = ConstU64<1>;
+    type RevealPeriod = ConstU64<5>;
+    type CommitForfeit = CommitForfeit;
+    type RewardPotId = AntiMevRewardPotId;
+    type ExecutorReward = ConstU128<100>;
+    type Randomness = TestRandomness;
+    type OffchainSignature = TestSignature;
+    type OffchainPublic = UintAuthorityId;
+    type MaxSignedIntents = ConstU32<8>;
+    type SolverBond = ConstU128<1_000>;
+    type BiddingPeriod = BiddingPeriod;
+    type SolverFee = ConstU128<50>;
+    // Added liquidity is locked for an epoch
+    type LiquidityHoldingPeriod = ConstU64<10>;
+    type PriceBand = PriceBand;
+    type DefaultImpactThreshold = DefaultImpactThreshold;
+    #[cfg(feature = "runtime-benchmarks")]
+    type BenchmarkHelper = AntiMevBenchmarkHelper;
+}
+
+/// Creates the benchmark assets next to the genesis ones.
+#[cfg(feature = "runtime-benchmarks")]
+pub struct AntiMevBenchmarkHelper;
+
+#[cfg(feature = "runtime-benchmarks")]
+impl pallet_anti_mev_amm::BenchmarkHelper<AssetId> for AntiMevBenchmarkHelper {
+    fn asset_id(seed: u32) -> AssetId {
+        1_000 + seed
+    }
+
+    fn create_asset(asset_id: &AssetId) {
+        <Assets as Create<u64>>::create(*asset_id, ACCOUNT_ALICE, true, 1)
+            .expect("benchmark asset is new");
+    }
 }
 
 pub(crate) const ACCOUNT_ALICE: u64 = 0;
@@ -0,0 +1,174 @@
+use super::*;
+use frame_system::pallet_prelude::BlockNumberFor;
+
+impl<T: Config> Pallet<T> {
+    /// Route a swap of `amount` for at least `limit`. The swap is executed on
+    /// the curve when the price impact on every pair it trades on is within
+    /// the threshold of the pair, and queued into the anti-mev batch otherwise.
+    /// A swap within the threshold is queued as well when it would move the
+    /// price beyond `PriceBand`, after other swaps of the block moved it.
+    ///
+    /// Returns the path the swap took.
+    pub(crate) fn do_swap(
+        caller: T::AccountId,
+        asset_in: SwapAsset<AssetIdOf<T>>,
+        asset_out: SwapAsset<AssetIdOf<T>>,
+        amount: BalanceOf<T>,
+        limit: BalanceOf<T>,
+        deadline: BlockNumberFor<T>,
+    ) -> Result<SwapPath, DispatchError> {
+        match (asset_in, asset_out) {
+            (SwapAsset::Currency, SwapAsset::Asset(asset_id)) => {
+                let pair = Self::get_pair(&asset_id)?;
+                let (currency_amount, token_amount) = Self::cp_compute_currency_to_asset(
+                    &pair,
+                    CpSwap::BasedInput { input_amount: amount, min_output: Zero::zero() },
+                )?;
+                let impact = Self::reserves_impact(
+                    &pair,
+                    pair.currency_reserve.saturating_add(currency_amount),
+                    pair.token_reserve.saturating_sub(token_amount),
+                );
+                if Self::within_threshold(&asset_id, impact) {
+                    ensure!(token_amount >= T::currency_to_asset(limit), Error::<T>::SlippageExceeded);
+                    Self::check_enough_currency(&caller, &currency_amount)?;
+                    if Self::try_instant(Self::do_cp_swap_currency_for_asset(
+                        pair,
+                        currency_amount,
+                        token_amount,
+                        caller.clone(),
+                        caller.clone(),
+                    ))? {
+                        return Ok(SwapPath::Instant);
+                    }
+                }
+                Self::queue_swap_currency_for_asset(
+                    caller,
+                    asset_id,
+                    AntiMevSwap::BasedInput {
+                        input_amount: amount,
+                        min_output: T::currency_to_asset(limit),
+                    },
+                    deadline,
+                )?;
+            }
+            (SwapAsset::Asset(asset_id), SwapAsset::Currency) => {
+                let pair = Self::get_pair(&asset_id)?;
+                let (currency_amount, token_amount) = Self::cp_compute_asset_to_currency_price(
+                    &pair,
+                    CpSwap::BasedInput {
+                        input_amount: T::currency_to_asset(amount),
+                        min_output: Zero::zero(),
+                    },
+                )?;
+                let impact = Self::reserves_impact(
+                    &pair,
+                    pair.currency_reserve.saturating_sub(currency_amount),
+                    pair.token_reserve.saturating_add(token_amount),
+                );
+                if Self::within_threshold(&asset_id, impact) {
+                    ensure!(currency_amount >= limit, Error::<T>::SlippageExceeded);
+                    Self::check_enough_tokens(&asset_id, &caller, &token_amount)?;
+                    if Self::try_instant(Self::do_cp_swap_asset_for_currency(
+                        pair,
+                        currency_amount,
+                        token_amount,
+                        caller.clone(),
+                        caller.clone(),
+                    ))? {
+                        return Ok(SwapPath::Instant);
+                    }
+                }
+                Self::queue_swap_asset_for_currency(
+                    caller,
+                    asset_id,
+                    AntiMevSwap::BasedInput { input_amount: token_amount, min_output: limit },
+                    deadline,
+                )?;
+            }
+            (SwapAsset::Asset(sold_asset_id), SwapAsset::Asset(bought_asset_id))
+                if sold_asset_id != bought_asset_id =>
+            {
+                let sold_pair = Self::get_pair(&sold_asset_id)?;
+                let bought_pair = Self::get_pair(&bought_asset_id)?;
+                let (sold_token_amount, currency_amount, bought_token_amount) =
+                    Self::cp_compute_asset_to_asset_price(
+                        &sold_pair,
+                        &bought_pair,
+                        CpSwap::BasedInput {
+                            input_amount: T::currency_to_asset(amount),
+                            min_output: Zero::zero(),
+                        },
+                    )?;
+                let sold_impact = Self::reserves_impact(
+                    &sold_pair,
+                    sold_pair.currency_reserve.saturating_sub(currency_amount),
+                    sold_pair.token_reserve.saturating_add(sold_token_amount),
+                );
+                let bought_impact = Self::reserves_impact(
+                    &bought_pair,
+                    bought_pair.currency_reserve.saturating_add(currency_amount),
+                    bought_pair.token_reserve.saturating_sub(bought_token_amount),
+                );
+                if Self::within_threshold(&sold_asset_id, sold_impact)
+                    && Self::within_threshold(&bought_asset_id, bought_impact)
+                {
+                    ensure!(
+                        bought_token_amount >= T::currency_to_asset(limit),
+                        Error::<T>::SlippageExceeded
+                    );
+                    Self::check_enough_tokens(&sold_asset_id, &caller, &sold_token_amount)?;
+                    if Self::try_instant(Self::do_cp_swap_asset_for_asset(
+                        sold_pair,
+                        bought_pair,
+                        currency_amount,
+                        sold_token_amount,
+                        bought_token_amount,
+                        caller.clone(),
+                    ))? {
+                        return Ok(SwapPath::Instant);
+                    }
+                }
+                Self::queue_swap_asset_for_asset(
+                    caller,
+                    sold_asset_id,
+                    bought_asset_id,
+                    sold_token_amount,
+                    T::currency_to_asset(limit),
+                    deadline,
+                )?;
+            }
+            _ => return Err(Error::<T>::InvalidSwapRoute.into()),
+        }
+        Ok(SwapPath::Batched)
+    }
+
+    /// The price impact above which `swap` queues the orders of the pair.
+    pub fn impact_threshold(asset_id: &AssetIdOf<T>) -> Perbill {
+        <ImpactThresholds<T>>::get(asset_id.clone()).unwrap_or_else(T::DefaultImpactThreshold::get)
+    }
+
+    fn within_threshold(asset_id: &AssetIdOf<T>, impact: FixedU128) -> bool {
+        impact <= FixedU128::from_perbill(Self::impact_threshold(asset_id))
+    }
+
+    /// The price impact of moving the pair to the given reserves.
+    fn reserves_impact(
+        pair: &PairOf<T>,
+        currency_reserve: BalanceOf<T>,
+        token_reserve: AssetBalanceOf<T>,
+    ) -> FixedU128 {
+        let moved = Pair { currency_reserve, token_reserve, ..pair.clone() };
+        Self::price_impact(Self::spot_price(pair), Self::spot_price(&moved))
+    }
+
+    /// Whether an instant swap was executed. A swap rejected by `PriceBand` was
+    /// rolled back and can still be queued, any other error fails the swap.
+    fn try_instant(result: DispatchResult) -> Result<bool, DispatchError> {
+        match result {
+            Ok(()) => Ok(true),
+            Err(e) if e == Error::<T>::PriceBandExceeded.into() => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use crate::types::SettlementPreview;
+use codec::Codec;
+use frame_support::sp_runtime::DispatchError;
+
+sp_api::decl_runtime_apis! {
+    /// Read-only access to the settlement of the anti-mev pairs.
+    pub trait AntiMevAmmApi<AssetId, AccountId, Balance, AssetBalance>
+    where
+        AssetId: Codec,
+        AccountId: Codec,
+        Balance: Codec,
+        AssetBalance: Codec,
+    {
+        /// The clearing prices, the reserves and the payout of each intent if
+        /// the queues of the pair were settled now.
+        fn simulate_settlement(
+            asset_id: AssetId,
+        ) -> Result<SettlementPreview<AccountId, Balance, AssetBalance>, DispatchError>;
+    }
+}
@@ -0,0 +1,394 @@
+use super::*;
+use frame_system::pallet_prelude::BlockNumberFor;
+
+impl<T: Config> Pallet<T> {
+    /// Settle the mature batch of the pair, in the style of a batch auction
+    /// when `BiddingPeriod` is not zero.
+    ///
+    /// The first call opens the bidding on the batch and settles nothing.
+    /// Once the bidding is over the best proposal is executed and its solver
+    /// is paid `SolverFee`. Without a proposal, or when the best one no longer
+    /// fits the batch, the batch is settled by `do_settle_and_distribute`.
+    ///
+    /// Returns the provider fee the settled batch paid, `None` when the
+    /// bidding was opened instead.
+    #[transactional]
+    pub(crate) fn do_settle_pair(
+        asset_id: &AssetIdOf<T>,
+    ) -> Result<Option<BalanceOf<T>>, DispatchError> {
+        if T::BiddingPeriod::get().is_zero() {
+            return Self::do_settle_and_distribute(asset_id).map(Some);
+        }
+        let now = <frame_system::Pallet<T>>::block_number();
+        let Some(bidding_end) = <BiddingEnd<T>>::get(asset_id.clone()) else {
+            Self::open_bidding(asset_id, now);
+            return Ok(None);
+        };
+        ensure!(now >= bidding_end, Error::<T>::BiddingOpen);
+        let fee = match <BestBids<T>>::get(asset_id.clone()) {
+            Some(bid) => match Self::do_settle_with_proposal(asset_id, &bid) {
+                Ok(fee) => {
+                    Self::pay_from_reward_pot(&bid.solver, T::SolverFee::get(), Event::SolverPaid);
+                    fee
+                }
+                Err(e) => {
+                    log::warn!(
+                        target: LOG_TARGET,
+                        "Proposal of {:?} no longer settles {:?}: {:?}",
+                        bid.solver,
+                        asset_id,
+                        e
+                    );
+                    Self::do_settle_and_distribute(asset_id)?
+                }
+            },
+            None => Self::do_settle_and_distribute(asset_id)?,
+        };
+        Ok(Some(fee))
+    }
+
+    /// Open the bidding on the mature batch of the pair. The batch is the one
+    /// of the intents queued so far, later intents wait for the next batch.
+    /// Returns the block the bidding ends.
+    pub(crate) fn open_bidding(
+        asset_id: &AssetIdOf<T>,
+        now: BlockNumberFor<T>,
+    ) -> BlockNumberFor<T> {
+        let (buys, expired_buys) = Self::batch_currency_intents(asset_id, now);
+        let (sells, expired_sells) = Self::batch_asset_intents(asset_id, now);
+        // the batch is part of a queue, so it fits in `MaxQueueLength`
+        let buy_ids = expired_buys.iter().chain(buys.iter()).map(|(intent_id, _)| *intent_id);
+        let sell_ids = expired_sells.iter().chain(sells.iter()).map(|(intent_id, _)| *intent_id);
+        <BiddingIntents<T>>::insert(
+            asset_id.clone(),
+            (
+                BoundedVec::truncate_from(buy_ids.collect()),
+                BoundedVec::truncate_from(sell_ids.collect()),
+            ),
+        );
+        let bidding_end = now.saturating_add(T::BiddingPeriod::get());
+        <BiddingEnd<T>>::insert(asset_id.clone(), bidding_end);
+        Self::deposit_event(Event::BiddingOpened(asset_id.clone(), bidding_end));
+        bidding_end
+    }
+
+    /// Whether solvers are still bidding on the batch of the pair.
+    pub(crate) fn is_bidding_open(asset_id: &AssetIdOf<T>, now: BlockNumberFor<T>) -> bool {
+        <BiddingEnd<T>>::get(asset_id.clone()).is_some_and(|bidding_end| now < bidding_end)
+    }
+
+    /// Whether the batch of the pair can be settled without the solvers: the
+    /// bidding is disabled, or it is over and no solver made a proposal.
+    pub(crate) fn settles_without_solvers(asset_id: &AssetIdOf<T>, now: BlockNumberFor<T>) -> bool {
+        T::BiddingPeriod::get().is_zero()
+            || (<BiddingEnd<T>>::get(asset_id.clone()).is_some_and(|bidding_end| now >= bidding_end)
+                && !<BestBids<T>>::contains_key(asset_id.clone()))
+    }
+
+    /// Keep the proposal of a solver for the mature batch of the pair if it
+    /// gives more surplus than the best proposal so far, and than the batch
+    /// cleared by the pallet itself.
+    pub(crate) fn do_submit_settlement(
+        solver: T::AccountId,
+        asset_id: &AssetIdOf<T>,
+        proposal: SettlementProposalOf<T>,
+    ) -> DispatchResult {
+        ensure!(!T::BiddingPeriod::get().is_zero(), Error::<T>::BiddingClosed);
+        let now = <frame_system::Pallet<T>>::block_number();
+        ensure!(Self::is_queue_mature(asset_id, now)?, Error::<T>::QueueTooSmall);
+        let bidding_end = <BiddingEnd<T>>::get(asset_id.clone())
+            .unwrap_or_else(|| Self::open_bidding(asset_id, now));
+        ensure!(now < bidding_end, Error::<T>::BiddingClosed);
+
+        let pair = Self::get_pair(asset_id)?;
+        let (buys, _) = Self::batch_currency_intents(asset_id, now);
+        let (sells, _) = Self::batch_asset_intents(asset_id, now);
+        let surplus = Self::check_proposal(&pair, &buys, &sells, &proposal)?;
+        // the pallet's own clearing pays no fee on the volume it nets at the
+        // spot price, its fills are scored without the invariant
+        let clearing_surplus = Self::clearing_proposal(&pair, &buys, &sells)
+            .and_then(|clearing| Self::proposal_surplus(&pair, &buys, &sells, &clearing))
+            .unwrap_or_else(|_| Zero::zero());
+        let best_surplus = <BestBids<T>>::get(asset_id.clone())
+            .map_or(clearing_surplus, |bid| bid.surplus.max(clearing_surplus));
+        ensure!(surplus > best_surplus, Error::<T>::ProposalNotBetter);
+
+        <BestBids<T>>::insert(asset_id.clone(), Bid { solver: solver.clone(), proposal, surplus });
+        Self::deposit_event(Event::SettlementProposed(asset_id.clone(), solver, surplus));
+        Ok(())
+    }
+
+    /// Settle the batch of the pair with the proposal of a solver.
+    /// The proposal is checked again against the batch, the intents past
+    /// their deadline have been refunded since it was made.
+    /// Returns the provider fee the fills paid.
+    #[transactional]
+    pub(crate) fn do_settle_with_proposal(
+        asset_id: &AssetIdOf<T>,
+        bid: &BidOf<T>,
+    ) -> Result<BalanceOf<T>, DispatchError> {
+        let now = <frame_system::Pallet<T>>::block_number();
+        let batch = Self::take_pair_batch(asset_id, now)?;
+        let proposal = &bid.proposal;
+        let surplus = Self::check_proposal(&batch.pair, &batch.buys, &batch.sells, proposal)?;
+
+        let mut buys = batch.buys.clone();
+        let mut sells = batch.sells.clone();
+        Self::shuffle(&mut buys, Self::batch_seed(asset_id, SwapDirection::CurrencyToAsset).0);
+        Self::shuffle(&mut sells, Self::batch_seed(asset_id, SwapDirection::AssetToCurrency).0);
+        for (intent_id, intent) in buys.iter() {
+            match proposal.buy_fills.iter().find(|fill| fill.intent_id == *intent_id) {
+                Some(fill) => {
+                    Self::do_anti_mev_swap_currency_for_asset(
+                        asset_id,
+                        *intent_id,
+                        intent,
+                        fill.amount_in,
+                        fill.amount_out,
+                    )?;
+                }
+                None => Self::do_anti_mev_refund_currency(
+                    asset_id,
+                    *intent_id,
+                    intent,
+                    Event::RefundedSwapCurrencyForAsset,
+                )?,
+            }
+        }
+        for (intent_id, intent) in sells.iter() {
+            match proposal.sell_fills.iter().find(|fill| fill.intent_id == *intent_id) {
+                Some(fill) => {
+                    Self::do_anti_mev_swap_asset_for_currency(
+                        asset_id,
+                        *intent_id,
+                        intent,
+                        fill.amount_in,
+                        fill.amount_out,
+                        true,
+                    )?;
+                }
+                None => Self::do_anti_mev_refund_asset(
+                    asset_id,
+                    *intent_id,
+                    intent,
+                    Event::RefundedSwapAssetForCurrency,
+                )?,
+            }
+        }
+
+        let mut pair = batch.pair.clone();
+        pair.currency_reserve = proposal.currency_reserve;
+        pair.token_reserve = proposal.token_reserve;
+        <Pairs<T>>::insert(asset_id.clone(), pair);
+        Self::close_pair_batch(&batch);
+        Self::deposit_event(Event::SettledByProposal(asset_id.clone(), bid.solver.clone(), surplus));
+
+        let currency_in = proposal.buy_fills.iter().fold(Zero::zero(), |total: BalanceOf<T>, fill| {
+            total.saturating_add(fill.amount_in)
+        });
+        let asset_in = proposal.sell_fills.iter().fold(Zero::zero(), |total: AssetBalanceOf<T>, fill| {
+            total.saturating_add(fill.amount_in)
+        });
+        Ok(Self::provider_fee(&batch.pair, currency_in, asset_in))
+    }
+
+    /// Check a proposal against the live intents of the batch:
+    ///
+    /// - it has at most one fill for each intent of the batch,
+    /// - the fills are valid, see `proposal_surplus`,
+    /// - the reserves are the reserves of the pair plus the inputs of the
+    ///   fills minus their outputs,
+    /// - the product of the reserves doesn't decrease when the inputs are
+    ///   counted net of the provider fee, as in `cp_get_output_amount`.
+    ///
+    /// Returns the surplus of the filled intents over their limits.
+    pub(crate) fn check_proposal(
+        pair: &PairOf<T>,
+        buys: &[(IntentId, CurrencyToAssetIntentOf<T>)],
+        sells: &[(IntentId, AssetToCurrencyIntentOf<T>)],
+        proposal: &SettlementProposalOf<T>,
+    ) -> Result<BalanceOf<T>, DispatchError> {
+        ensure!(
+            proposal.buy_fills.len() <= buys.len() && proposal.sell_fills.len() <= sells.len(),
+            Error::<T>::ProposalTooLarge
+        );
+        let surplus = Self::proposal_surplus(pair, buys, sells, proposal)?;
+
+        let mut currency_in: BalanceOf<T> = Zero::zero();
+        let mut currency_out: BalanceOf<T> = Zero::zero();
+        let mut asset_in: AssetBalanceOf<T> = Zero::zero();
+        let mut asset_out: AssetBalanceOf<T> = Zero::zero();
+        for fill in proposal.buy_fills.iter() {
+            currency_in = currency_in.checked_add(&fill.amount_in).ok_or(Error::<T>::Overflow)?;
+            asset_out = asset_out.checked_add(&fill.amount_out).ok_or(Error::<T>::Overflow)?;
+        }
+        for fill in proposal.sell_fills.iter() {
+            asset_in = asset_in.checked_add(&fill.amount_in).ok_or(Error::<T>::Overflow)?;
+            currency_out = currency_out.checked_add(&fill.amount_out).ok_or(Error::<T>::Overflow)?;
+        }
+
+        let currency_reserve = pair
+            .currency_reserve
+            .checked_add(&currency_in)
+            .and_then(|reserve| reserve.checked_sub(&currency_out));
+        let token_reserve = pair
+            .token_reserve
+            .checked_add(&asset_in)
+            .and_then(|reserve| reserve.checked_sub(&asset_out));
+        ensure!(
+            currency_reserve == Some(proposal.currency_reserve)
+                && token_reserve == Some(proposal.token_reserve),
+            Error::<T>::ReservesMismatch
+        );
+
+        // the provider fee stays in the reserves, only the rest of the inputs trades
+        let net_of_fee = |amount: BalanceOf<T>| {
+            amount
+                .checked_mul(&T::net_amount_numerator())
+                .map(|amount| amount / T::ProviderFeeDenominator::get())
+        };
+        let traded_currency_reserve = net_of_fee(currency_in)
+            .and_then(|currency_in| pair.currency_reserve.checked_add(&currency_in))
+            .and_then(|reserve| reserve.checked_sub(&currency_out))
+            .ok_or(Error::<T>::InvariantBroken)?;
+        let traded_token_reserve = net_of_fee(T::asset_to_currency(asset_in))
+            .and_then(|asset_in| T::asset_to_currency(pair.token_reserve).checked_add(&asset_in))
+            .and_then(|reserve| reserve.checked_sub(&T::asset_to_currency(asset_out)))
+            .ok_or(Error::<T>::InvariantBroken)?;
+        let product_before = pair
+            .currency_reserve
+            .checked_mul(&T::asset_to_currency(pair.token_reserve))
+            .ok_or(Error::<T>::Overflow)?;
+        let product_after = traded_currency_reserve
+            .checked_mul(&traded_token_reserve)
+            .ok_or(Error::<T>::Overflow)?;
+        ensure!(product_after >= product_before, Error::<T>::InvariantBroken);
+        Ok(surplus)
+    }
+
+    /// Check the fills of a proposal against the live intents of the batch:
+    ///
+    /// - every fill is for a different intent of the batch,
+    /// - an exact input intent is filled with its whole input, an exact
+    ///   output intent with its whole output,
+    /// - no fill takes more than the input locked by its intent,
+    /// - no fill pays less than the limit of its intent.
+    ///
+    /// Returns the surplus of the filled intents over their limits: the
+    /// output above the minimum output of an exact input intent, the input
+    /// below the maximum input of an exact output intent, valued in currency
+    /// at the spot price of the pair.
+    pub(crate) fn proposal_surplus(
+        pair: &PairOf<T>,
+        buys: &[(IntentId, CurrencyToAssetIntentOf<T>)],
+        sells: &[(IntentId, AssetToCurrencyIntentOf<T>)],
+        proposal: &SettlementProposalOf<T>,
+    ) -> Result<BalanceOf<T>, DispatchError> {
+        let mut filled: Vec<IntentId> = Vec::new();
+        let mut surplus: BalanceOf<T> = Zero::zero();
+
+        for fill in proposal.buy_fills.iter() {
+            let (_, intent) = buys
+                .iter()
+                .find(|(intent_id, _)| *intent_id == fill.intent_id)
+                .ok_or(Error::<T>::InvalidFill)?;
+            ensure!(!filled.contains(&fill.intent_id), Error::<T>::InvalidFill);
+            ensure!(fill.amount_in <= intent.swap.locked_input(), Error::<T>::InvalidFill);
+            ensure!(fill.amount_out >= intent.swap.min_output(), Error::<T>::FillBelowLimit);
+            filled.push(fill.intent_id);
+            let fill_surplus = match intent.swap {
+                AntiMevSwap::BasedInput { input_amount, min_output } => {
+                    ensure!(fill.amount_in == input_amount, Error::<T>::InvalidFill);
+                    Self::currency_value(pair, fill.amount_out - min_output)
+                }
+                AntiMevSwap::BasedOutput { max_input, output_amount } => {
+                    ensure!(fill.amount_out == output_amount, Error::<T>::InvalidFill);
+                    Some(max_input - fill.amount_in)
+                }
+            };
+            surplus = fill_surplus
+                .and_then(|fill_surplus| surplus.checked_add(&fill_surplus))
+                .ok_or(Error::<T>::Overflow)?;
+        }
+        for fill in proposal.sell_fills.iter() {
+            let (_, intent) = sells
+                .iter()
+                .find(|(intent_id, _)| *intent_id == fill.intent_id)
+                .ok_or(Error::<T>::InvalidFill)?;
+            ensure!(!filled.contains(&fill.intent_id), Error::<T>::InvalidFill);
+            ensure!(fill.amount_in <= intent.swap.locked_input(), Error::<T>::InvalidFill);
+            ensure!(fill.amount_out >= intent.swap.min_output(), Error::<T>::FillBelowLimit);
+            filled.push(fill.intent_id);
+            let fill_surplus = match intent.swap {
+                AntiMevSwap::BasedInput { input_amount, min_output } => {
+                    ensure!(fill.amount_in == input_amount, Error::<T>::InvalidFill);
+                    Some(fill.amount_out - min_output)
+                }
+                AntiMevSwap::BasedOutput { max_input, output_amount } => {
+                    ensure!(fill.amount_out == output_amount, Error::<T>::InvalidFill);
+                    Self::currency_value(pair, max_input - fill.amount_in)
+                }
+            };
+            surplus = fill_surplus
+                .and_then(|fill_surplus| surplus.checked_add(&fill_surplus))
+                .ok_or(Error::<T>::Overflow)?;
+        }
+        Ok(surplus)
+    }
+
+    /// The batch cleared by the pallet itself, as a proposal.
+    pub(crate) fn clearing_proposal(
+        pair: &PairOf<T>,
+        buys: &[(IntentId, CurrencyToAssetIntentOf<T>)],
+        sells: &[(IntentId, AssetToCurrencyIntentOf<T>)],
+    ) -> Result<SettlementProposalOf<T>, DispatchError> {
+        let cleared = Self::clear_intents(pair, buys.to_vec(), sells.to_vec())?;
+        let (currency_out, asset_out) = cleared.outputs(pair)?;
+        let mut currency_reserve = pair.currency_reserve;
+        let mut token_reserve = pair.token_reserve;
+        let buy_fills = cleared
+            .buys
+            .iter()
+            .map(|(intent_id, intent)| {
+                let (amount_in, amount_out) = Self::currency_for_asset_payout(
+                    intent,
+                    cleared.buy_input(intent_id, intent),
+                    &asset_out,
+                    &cleared.total_cumulative_currency,
+                )?;
+                currency_reserve = currency_reserve.saturating_add(amount_in);
+                token_reserve = token_reserve.saturating_sub(amount_out);
+                Ok(Fill { intent_id: *intent_id, amount_in, amount_out })
+            })
+            .collect::<Result<Vec<_>, ArithmeticError>>()?;
+        let sell_fills = cleared
+            .sells
+            .iter()
+            .map(|(intent_id, intent)| {
+                let (amount_in, amount_out) = Self::asset_for_currency_payout(
+                    intent,
+                    cleared.sell_input(intent_id, intent),
+                    &T::currency_to_asset(currency_out),
+                    &T::currency_to_asset(cleared.total_cumulative_asset),
+                )?;
+                token_reserve = token_reserve.saturating_add(amount_in);
+                currency_reserve = currency_reserve.saturating_sub(amount_out);
+                Ok(Fill { intent_id: *intent_id, amount_in, amount_out })
+            })
+            .collect::<Result<Vec<_>, ArithmeticError>>()?;
+        // the fills are a part of the batch, which is a part of the queues
+        Ok(SettlementProposal {
+            buy_fills: buy_fills.try_into().map_err(|_| Error::<T>::ProposalTooLarge)?,
+            sell_fills: sell_fills.try_into().map_err(|_| Error::<T>::ProposalTooLarge)?,
+            currency_reserve,
+            token_reserve,
+        })
+    }
+
+    /// The value of an amount of asset in currency at the spot price of the pair.
+    pub(crate) fn currency_value(pair: &PairOf<T>, asset_amount: AssetBalanceOf<T>) -> Option<BalanceOf<T>> {
+        T::asset_to_currency(asset_amount)
+            .checked_mul(&pair.currency_reserve)
+            .map(|value| value / T::asset_to_currency(pair.token_reserve))
+    }
+}
@@ -2,7 +2,9 @@ use super::{
 	mock::*,
 	*,
 };
-use frame_support::assert_ok;
+use frame_support::{assert_noop, assert_ok, traits::Hooks};
+use sp_core::H256;
+use sp_runtime::{bounded_vec, testing::TestSignature};
 
 #[test]
 fn should_see_attacker_manipulate_market() {
@@ -36,12 +38,15 @@ fn should_see_attacker_manipulate_market() {
         let attacker_after_asset_b_balance = <TestRuntime as Config>::Assets::balance(ASSET_B.clone(), &ACCOUNT_ATTACKER);
         assert!(attacker_after_asset_b_balance > attacker_initial_asset_b_balance);
 
-        // Bob swap
+        // Bob swap, large enough for the price move to outweigh the fees
         assert_ok!(AntiMevAmm::cp_swap_asset_to_asset(
             RuntimeOrigin::signed(ACCOUNT_BOB),
             ASSET_A,
             ASSET_B,
-            buy_op,
+            CpSwap::BasedInput { 
+                input_amount: INIT_LIQUIDITY / 10,
+                min_output: 10,
+            },
             System::block_number().saturating_add(1)
         ));
 
@@ -68,6 +73,221 @@ fn should_see_attacker_manipulate_market() {
 	});
 }
 
+#[test]
+fn should_reject_instant_swap_beyond_price_band() {
+    new_test_ext().execute_with(|| {
+        PriceBand::set(Some(Perbill::from_percent(5)));
+        let buy = |amount| {
+            AntiMevAmm::cp_swap_currency_for_asset(
+                RuntimeOrigin::signed(ACCOUNT_ATTACKER),
+                ASSET_A,
+                CpSwap::BasedInput { input_amount: amount, min_output: 1 },
+                System::block_number().saturating_add(1),
+            )
+        };
+
+        // a large front-run would move the price by ~20%
+        assert_noop!(buy(INIT_LIQUIDITY / 10), Error::<TestRuntime>::PriceBandExceeded);
+
+        // smaller swaps pass until they add up beyond the band within the block
+        assert_ok!(buy(INIT_LIQUIDITY / 100));
+        assert_ok!(buy(INIT_LIQUIDITY / 100));
+        assert_noop!(buy(INIT_LIQUIDITY / 100), Error::<TestRuntime>::PriceBandExceeded);
+        assert_noop!(
+            AntiMevAmm::cp_swap_asset_for_currency(
+                RuntimeOrigin::signed(ACCOUNT_ATTACKER),
+                ASSET_A,
+                CpSwap::BasedInput { input_amount: INIT_LIQUIDITY / 10, min_output: 1 },
+                System::block_number().saturating_add(1),
+            ),
+            Error::<TestRuntime>::PriceBandExceeded
+        );
+
+        // the next block measures from the new price
+        System::set_block_number(System::block_number() + 1);
+        assert_ok!(buy(INIT_LIQUIDITY / 100));
+
+        // large trades still go through the anti-mev queue
+        assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            INIT_LIQUIDITY / 10,
+            1,
+            System::block_number().saturating_add(1)
+        ));
+    });
+}
+
+#[test]
+fn should_route_swap_by_price_impact() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(AntiMevAmm::create_pair(
+            RuntimeOrigin::signed(ACCOUNT_ALICE),
+            ASSET_B,
+            LIQ_TOKEN_B,
+            INIT_LIQUIDITY,
+            INIT_LIQUIDITY
+        ));
+        let swap = |who, asset_in, asset_out, amount| {
+            AntiMevAmm::swap(
+                RuntimeOrigin::signed(who),
+                asset_in,
+                asset_out,
+                amount,
+                1,
+                System::block_number().saturating_add(1),
+            )
+        };
+
+        let holdings = |who| {
+            (
+                Balances::free_balance(who),
+                <TestRuntime as Config>::Assets::balance(ASSET_A, who),
+                <TestRuntime as Config>::Assets::balance(ASSET_B, who),
+            )
+        };
+        let reserves = |asset_id| {
+            let pair = AntiMevAmm::pairs(asset_id).expect("pair exists");
+            (pair.currency_reserve, pair.token_reserve)
+        };
+
+        // a small order is executed on the curve
+        let (currency_before, asset_a_before, _) = holdings(ACCOUNT_BOB);
+        let (currency_reserve, token_reserve) = reserves(ASSET_A);
+        assert_ok!(swap(ACCOUNT_BOB, SwapAsset::Currency, SwapAsset::Asset(ASSET_A), 1_000_000));
+        System::assert_last_event(RuntimeEvent::AntiMevAmm(Event::Swapped(
+            ACCOUNT_BOB,
+            SwapAsset::Currency,
+            SwapAsset::Asset(ASSET_A),
+            1_000_000,
+            SwapPath::Instant,
+        )));
+        let bought = token_reserve - reserves(ASSET_A).1;
+        assert!(bought > 0);
+        assert_eq!(reserves(ASSET_A).0, currency_reserve + 1_000_000);
+        assert_eq!(holdings(ACCOUNT_BOB).0, currency_before - 1_000_000);
+        assert_eq!(holdings(ACCOUNT_BOB).1, asset_a_before + bought);
+        assert!(is_queue_empty(ASSET_A));
+
+        let (currency_before, asset_a_before, _) = holdings(ACCOUNT_BOB);
+        let (currency_reserve, token_reserve) = reserves(ASSET_A);
+        assert_ok!(swap(ACCOUNT_BOB, SwapAsset::Asset(ASSET_A), SwapAsset::Currency, 1_000_000));
+        System::assert_last_event(RuntimeEvent::AntiMevAmm(Event::Swapped(
+            ACCOUNT_BOB,
+            SwapAsset::Asset(ASSET_A),
+            SwapAsset::Currency,
+            1_000_000,
+            SwapPath::Instant,
+        )));
+        let sold_for = currency_reserve - reserves(ASSET_A).0;
+        assert!(sold_for > 0);
+        assert_eq!(reserves(ASSET_A).1, token_reserve + 1_000_000);
+        assert_eq!(holdings(ACCOUNT_BOB).0, currency_before + sold_for);
+        assert_eq!(holdings(ACCOUNT_BOB).1, asset_a_before - 1_000_000);
+        assert!(is_queue_empty(ASSET_A));
+
+        let (currency_before, asset_a_before, asset_b_before) = holdings(ACCOUNT_BOB);
+        let (_, token_a_reserve) = reserves(ASSET_A);
+        let (_, token_b_reserve) = reserves(ASSET_B);
+        assert_ok!(swap(ACCOUNT_BOB, SwapAsset::Asset(ASSET_A), SwapAsset::Asset(ASSET_B), 1_000_000));
+        System::assert_last_event(RuntimeEvent::AntiMevAmm(Event::Swapped(
+            ACCOUNT_BOB,
+            SwapAsset::Asset(ASSET_A),
+            SwapAsset::Asset(ASSET_B),
+            1_000_000,
+            SwapPath::Instant,
+        )));
+        let bought = token_b_reserve - reserves(ASSET_B).1;
+        assert!(bought > 0);
+        assert_eq!(reserves(ASSET_A).1, token_a_reserve + 1_000_000);
+        assert_eq!(holdings(ACCOUNT_BOB), (currency_before, asset_a_before - 1_000_000, asset_b_before + bought));
+
+        // an order moving the price beyond the threshold is queued
+        let (currency_before, asset_a_before, _) = holdings(ACCOUNT_BOB);
+        assert_ok!(swap(ACCOUNT_BOB, SwapAsset::Asset(ASSET_A), SwapAsset::Currency, INIT_LIQUIDITY / 10));
+        System::assert_last_event(RuntimeEvent::AntiMevAmm(Event::Swapped(
+            ACCOUNT_BOB,
+            SwapAsset::Asset(ASSET_A),
+            SwapAsset::Currency,
+            INIT_LIQUIDITY / 10,
+            SwapPath::Batched,
+        )));
+        assert!(!is_queue_empty(ASSET_A));
+        assert_eq!(holdings(ACCOUNT_BOB).0, currency_before);
+        assert_eq!(holdings(ACCOUNT_BOB).1, asset_a_before - INIT_LIQUIDITY / 10);
+
+        // the threshold is set per pair
+        assert_noop!(
+            AntiMevAmm::set_impact_threshold(
+                RuntimeOrigin::signed(ACCOUNT_ATTACKER),
+                ASSET_B,
+                Some(Perbill::from_percent(50))
+            ),
+            sp_runtime::DispatchError::BadOrigin
+        );
+        assert_ok!(AntiMevAmm::set_impact_threshold(
+            RuntimeOrigin::root(),
+            ASSET_B,
+            Some(Perbill::from_percent(50))
+        ));
+        assert_eq!(AntiMevAmm::impact_threshold(&ASSET_B), Perbill::from_percent(50));
+        assert_ok!(swap(ACCOUNT_BOB, SwapAsset::Currency, SwapAsset::Asset(ASSET_B), INIT_LIQUIDITY / 10));
+        System::assert_last_event(RuntimeEvent::AntiMevAmm(Event::Swapped(
+            ACCOUNT_BOB,
+            SwapAsset::Currency,
+            SwapAsset::Asset(ASSET_B),
+            INIT_LIQUIDITY / 10,
+            SwapPath::Instant,
+        )));
+        assert!(is_queue_empty(ASSET_B));
+
+        // the swap has to trade two different sides
+        assert_noop!(
+            swap(ACCOUNT_BOB, SwapAsset::Currency, SwapAsset::Currency, 1_000_000),
+            Error::<TestRuntime>::InvalidSwapRoute
+        );
+        assert_noop!(
+            swap(ACCOUNT_BOB, SwapAsset::Asset(ASSET_A), SwapAsset::Asset(ASSET_A), 1_000_000),
+            Error::<TestRuntime>::InvalidSwapRoute
+        );
+    });
+}
+
+#[test]
+fn should_queue_swap_within_threshold_beyond_price_band() {
+    new_test_ext().execute_with(|| {
+        PriceBand::set(Some(Perbill::from_percent(2)));
+        assert_ok!(AntiMevAmm::set_impact_threshold(
+            RuntimeOrigin::root(),
+            ASSET_A,
+            Some(Perbill::from_percent(5))
+        ));
+        let buy = || {
+            AntiMevAmm::swap(
+                RuntimeOrigin::signed(ACCOUNT_BOB),
+                SwapAsset::Currency,
+                SwapAsset::Asset(ASSET_A),
+                INIT_LIQUIDITY / 200,
+                1,
+                System::block_number().saturating_add(1),
+            )
+        };
+
+        // the first order keeps the price within the band, the second one does not
+        assert_ok!(buy());
+        assert!(is_queue_empty(ASSET_A));
+        assert_ok!(buy());
+        System::assert_last_event(RuntimeEvent::AntiMevAmm(Event::Swapped(
+            ACCOUNT_BOB,
+            SwapAsset::Currency,
+            SwapAsset::Asset(ASSET_A),
+            INIT_LIQUIDITY / 200,
+            SwapPath::Batched,
+        )));
+        assert!(!is_queue_empty(ASSET_A));
+    });
+}
+
 #[test]
 fn should_demo_full_flow_anti_mev_with_currency() {
     new_test_ext().execute_with(|| {
@@ -77,6 +297,7 @@ fn should_demo_full_flow_anti_mev_with_currency() {
             RuntimeOrigin::signed(ACCOUNT_ATTACKER),
             ASSET_A,
             amount_in.clone(),
+            1,
             System::block_number().saturating_add(1)
         ));
 
@@ -85,6 +306,7 @@ fn should_demo_full_flow_anti_mev_with_currency() {
             RuntimeOrigin::signed(ACCOUNT_BOB),
             ASSET_A,
             amount_in.clone(),
+            1,
             System::block_number().saturating_add(1)
         ));
 
@@ -93,6 +315,7 @@ fn should_demo_full_flow_anti_mev_with_currency() {
             RuntimeOrigin::signed(ACCOUNT_ALICE),
             ASSET_A,
             amount_in.clone(),
+            1,
             System::block_number().saturating_add(1)
         ));
 
@@ -101,6 +324,7 @@ fn should_demo_full_flow_anti_mev_with_currency() {
             RuntimeOrigin::signed(ACCOUNT_ATTACKER),
             ASSET_A,
             amount_in.clone(),
+            1,
             System::block_number().saturating_add(1)
         ));
 
@@ -127,7 +351,7 @@ fn should_demo_full_flow_anti_mev_with_asset() {
             INIT_LIQUIDITY
         ));
 
-        let amount_in = 100;
+        let amount_in = 1_000_000_000;
         // A -> Currency: 0
         // Currency -> A: 0
         // Currency -> B: 0
@@ -139,11 +363,12 @@ fn should_demo_full_flow_anti_mev_with_asset() {
             ASSET_A,
             ASSET_B,
             amount_in.clone(),
-            System::block_number().saturating_add(1)
+            1,
+            System::block_number().saturating_add(100)
         ));
         // A -> Currency: 1
         // Currency -> A: 0
-        // Currency -> B: 1
+        // Currency -> B: 0
         // B -> Currency: 0
         let pair_asset_a_queue = AntiMevAmm::get_pair_asset_queue(&ASSET_A).expect("");
         let pair_currency_a_queue = AntiMevAmm::get_pair_currency_queue(&ASSET_A).expect("");
@@ -151,7 +376,7 @@ fn should_demo_full_flow_anti_mev_with_asset() {
         let pair_currency_b_queue = AntiMevAmm::get_pair_currency_queue(&ASSET_B).expect("");
         assert!(pair_asset_a_queue.len() == 1);
         assert!(pair_currency_a_queue.len() == 0);
-        assert!(pair_currency_b_queue.len() == 1);
+        assert!(pair_currency_b_queue.is_empty());
         assert!(pair_asset_b_queue.len() == 0);
 
         // Bob buy
@@ -160,74 +385,1913 @@ fn should_demo_full_flow_anti_mev_with_asset() {
             ASSET_A,
             ASSET_B,
             amount_in.clone(),
-            System::block_number().saturating_add(1)
+            1,
+            System::block_number().saturating_add(100)
         ));
-        // A -> Currency: 2
-        // Currency -> A: 0
-        // Currency -> B: 2
-        // B -> Currency: 0
-        let pair_asset_a_queue = AntiMevAmm::get_pair_asset_queue(&ASSET_A).expect("");
-        let pair_currency_a_queue = AntiMevAmm::get_pair_currency_queue(&ASSET_A).expect("");
-        let pair_asset_b_queue = AntiMevAmm::get_pair_asset_queue(&ASSET_B).expect("");
-        let pair_currency_b_queue = AntiMevAmm::get_pair_currency_queue(&ASSET_B).expect("");
-        assert!(pair_asset_a_queue.len() == 2);
-        assert!(pair_currency_a_queue.len() == 0);
-        assert!(pair_currency_b_queue.len() == 2);
-        assert!(pair_asset_b_queue.len() == 0);
-
         // Alice sell
         assert_ok!(AntiMevAmm::add_swap_asset_for_asset(
             RuntimeOrigin::signed(ACCOUNT_ALICE),
             ASSET_B,
             ASSET_A,
             amount_in.clone(),
-            System::block_number().saturating_add(1)
+            1,
+            System::block_number().saturating_add(100)
         ));
-        // A -> Currency: 2
-        // Currency -> A: 1
-        // Currency -> B: 2
-        // B -> Currency: 1
-        let pair_asset_a_queue = AntiMevAmm::get_pair_asset_queue(&ASSET_A).expect("");
-        let pair_currency_a_queue = AntiMevAmm::get_pair_currency_queue(&ASSET_A).expect("");
-        let pair_asset_b_queue = AntiMevAmm::get_pair_asset_queue(&ASSET_B).expect("");
-        let pair_currency_b_queue = AntiMevAmm::get_pair_currency_queue(&ASSET_B).expect("");
-        assert!(pair_asset_a_queue.len() == 2);
-        assert!(pair_currency_a_queue.len() == 1);
-        assert!(pair_currency_b_queue.len() == 2);
-        assert!(pair_asset_b_queue.len() == 1);
-
         // Attacker sell
         assert_ok!(AntiMevAmm::add_swap_asset_for_asset(
             RuntimeOrigin::signed(ACCOUNT_ATTACKER),
             ASSET_B,
             ASSET_A,
             amount_in.clone(),
-            System::block_number().saturating_add(1)
+            1,
+            System::block_number().saturating_add(100)
         ));
         // A -> Currency: 2
-        // Currency -> A: 2
-        // Currency -> B: 2
+        // Currency -> A: 0
+        // Currency -> B: 0
         // B -> Currency: 2
         let pair_asset_a_queue = AntiMevAmm::get_pair_asset_queue(&ASSET_A).expect("");
         let pair_currency_a_queue = AntiMevAmm::get_pair_currency_queue(&ASSET_A).expect("");
         let pair_asset_b_queue = AntiMevAmm::get_pair_asset_queue(&ASSET_B).expect("");
         let pair_currency_b_queue = AntiMevAmm::get_pair_currency_queue(&ASSET_B).expect("");
         assert!(pair_asset_a_queue.len() == 2);
-        assert!(pair_currency_a_queue.len() == 2);
-        assert!(pair_currency_b_queue.len() == 2);
+        assert!(pair_currency_a_queue.len() == 0);
+        assert!(pair_currency_b_queue.is_empty());
         assert!(pair_asset_b_queue.len() == 2);
 
-        // Trigger settlement
+        // Trigger settlement once the epoch is over
+        System::set_block_number(1 + <<TestRuntime as Config>::EpochLength as Get<u64>>::get());
         assert_ok!(AntiMevAmm::settle_and_distribute(
             RuntimeOrigin::signed(ACCOUNT_BOB),
             ASSET_A,
         ));
+        // A -> Currency: 0
+        // Currency -> A: 0
+        // Currency -> B: 2
+        // B -> Currency: 2
+        assert!(AntiMevAmm::get_pair_currency_queue(&ASSET_B).expect("").len() == 2);
         assert_ok!(AntiMevAmm::settle_and_distribute(
             RuntimeOrigin::signed(ACCOUNT_BOB),
             ASSET_B,
         ));
+        // A -> Currency: 0
+        // Currency -> A: 2
+        // Currency -> B: 0
+        // B -> Currency: 0
+        assert!(AntiMevAmm::get_pair_currency_queue(&ASSET_A).expect("").len() == 2);
+
+        System::set_block_number(System::block_number() + <<TestRuntime as Config>::EpochLength as Get<u64>>::get());
+        assert_ok!(AntiMevAmm::settle_and_distribute(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+        ));
+        assert!(is_queue_empty(ASSET_A));
+        assert!(is_queue_empty(ASSET_B));
 
         // Compare the received values
         // Look at the event log for the final balances 😂😂😂
     });
 }
+
+#[test]
+fn should_remove_liquidity_pro_rata() {
+    new_test_ext().execute_with(|| {
+        let pallet_account = <TestRuntime as ConfigHelper>::pallet_account();
+        let alice_currency_before = Balances::free_balance(ACCOUNT_ALICE);
+        let alice_asset_before = <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_ALICE);
+        let liquidity_amount = INIT_LIQUIDITY / 4;
+
+        assert_ok!(AntiMevAmm::remove_liquidity(
+            RuntimeOrigin::signed(ACCOUNT_ALICE),
+            ASSET_A,
+            liquidity_amount,
+            1,
+            1,
+            System::block_number().saturating_add(1)
+        ));
+
+        let pair = AntiMevAmm::pairs(ASSET_A).expect("pair exists");
+        assert_eq!(pair.currency_reserve, INIT_LIQUIDITY - liquidity_amount);
+        assert_eq!(pair.token_reserve, INIT_LIQUIDITY - liquidity_amount);
+        assert_eq!(Balances::free_balance(ACCOUNT_ALICE), alice_currency_before + liquidity_amount);
+        assert_eq!(
+            <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_ALICE),
+            alice_asset_before + liquidity_amount
+        );
+        assert_eq!(
+            <TestRuntime as Config>::AssetRegistry::balance(LIQ_TOKEN_A, ACCOUNT_ALICE),
+            INIT_LIQUIDITY - liquidity_amount
+        );
+        assert_eq!(Balances::free_balance(pallet_account), INIT_LIQUIDITY - liquidity_amount);
+
+        // slippage protection and ownership checks
+        assert_noop!(
+            AntiMevAmm::remove_liquidity(
+                RuntimeOrigin::signed(ACCOUNT_ALICE),
+                ASSET_A,
+                liquidity_amount,
+                liquidity_amount + 1,
+                1,
+                System::block_number().saturating_add(1)
+            ),
+            Error::<TestRuntime>::MinCurrencyTooHigh
+        );
+        assert_noop!(
+            AntiMevAmm::remove_liquidity(
+                RuntimeOrigin::signed(ACCOUNT_BOB),
+                ASSET_A,
+                liquidity_amount,
+                1,
+                1,
+                System::block_number().saturating_add(1)
+            ),
+            Error::<TestRuntime>::ProviderLiquidityTooLow
+        );
+    });
+}
+
+#[test]
+fn should_not_remove_liquidity_backing_queued_intents() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            100,
+            1,
+            System::block_number().saturating_add(1)
+        ));
+
+        // the last provider can't leave while intents wait for the reserves
+        assert_noop!(
+            AntiMevAmm::remove_liquidity(
+                RuntimeOrigin::signed(ACCOUNT_ALICE),
+                ASSET_A,
+                INIT_LIQUIDITY,
+                1,
+                1,
+                System::block_number().saturating_add(1)
+            ),
+            Error::<TestRuntime>::PendingSwapIntents
+        );
+
+        // a partial withdrawal is paid from the reserves only
+        let pallet_account = <TestRuntime as ConfigHelper>::pallet_account();
+        assert_ok!(AntiMevAmm::remove_liquidity(
+            RuntimeOrigin::signed(ACCOUNT_ALICE),
+            ASSET_A,
+            INIT_LIQUIDITY / 2,
+            1,
+            1,
+            System::block_number().saturating_add(1)
+        ));
+        assert_eq!(Balances::free_balance(pallet_account), INIT_LIQUIDITY / 2 + 100);
+        assert_eq!(AntiMevAmm::pending_inputs(ASSET_A), (100, 0));
+
+        assert_ok!(AntiMevAmm::cancel_swap_intent(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            SwapDirection::CurrencyToAsset
+        ));
+        assert_eq!(AntiMevAmm::pending_inputs(ASSET_A), (0, 0));
+    });
+}
+
+#[test]
+fn should_not_remove_liquidity_below_pending_inputs_of_the_pair() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(AntiMevAmm::create_pair(
+            RuntimeOrigin::signed(ACCOUNT_ALICE),
+            ASSET_B,
+            LIQ_TOKEN_B,
+            INIT_LIQUIDITY,
+            INIT_LIQUIDITY
+        ));
+        assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            INIT_LIQUIDITY / 2,
+            1,
+            System::block_number().saturating_add(1)
+        ));
+        assert_eq!(AntiMevAmm::pending_inputs(ASSET_A), (INIT_LIQUIDITY / 2, 0));
+
+        // the pallet account also holds the other pair, which doesn't back this one
+        assert_noop!(
+            AntiMevAmm::remove_liquidity(
+                RuntimeOrigin::signed(ACCOUNT_ALICE),
+                ASSET_A,
+                INIT_LIQUIDITY * 3 / 4,
+                1,
+                1,
+                System::block_number().saturating_add(1)
+            ),
+            Error::<TestRuntime>::PendingSwapIntents
+        );
+
+        // the reserves left still cover the pending input at the spot price
+        assert_ok!(AntiMevAmm::remove_liquidity(
+            RuntimeOrigin::signed(ACCOUNT_ALICE),
+            ASSET_A,
+            INIT_LIQUIDITY / 2,
+            1,
+            1,
+            System::block_number().saturating_add(1)
+        ));
+        let pair = AntiMevAmm::pairs(ASSET_A).expect("pair exists");
+        assert_eq!(pair.token_reserve, INIT_LIQUIDITY / 2);
+    });
+}
+
+#[test]
+fn should_lock_added_liquidity_for_holding_period() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(AntiMevAmm::add_liquidity(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            1_000_000,
+            1,
+            2_000_000,
+            System::block_number().saturating_add(1)
+        ));
+        let minted = <TestRuntime as Config>::AssetRegistry::balance(LIQ_TOKEN_A, ACCOUNT_BOB);
+        assert_eq!(AntiMevAmm::locked_liquidity(&LIQ_TOKEN_A, &ACCOUNT_BOB), minted);
+
+        // the liquidity can neither be removed nor moved to another account
+        assert_noop!(
+            AntiMevAmm::remove_liquidity(
+                RuntimeOrigin::signed(ACCOUNT_BOB),
+                ASSET_A,
+                minted,
+                1,
+                1,
+                System::block_number().saturating_add(1)
+            ),
+            Error::<TestRuntime>::LiquidityLocked
+        );
+        assert!(Assets::transfer(RuntimeOrigin::signed(ACCOUNT_BOB), LIQ_TOKEN_A, ACCOUNT_ERWIN, minted).is_err());
+
+        let holding_period = <<TestRuntime as Config>::LiquidityHoldingPeriod as Get<u64>>::get();
+        System::set_block_number(System::block_number() + holding_period);
+        assert_eq!(AntiMevAmm::locked_liquidity(&LIQ_TOKEN_A, &ACCOUNT_BOB), 0);
+        assert_ok!(AntiMevAmm::remove_liquidity(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            minted,
+            1,
+            1,
+            System::block_number().saturating_add(1)
+        ));
+        assert!(AntiMevAmm::liquidity_locks(LIQ_TOKEN_A, ACCOUNT_BOB).is_none());
+    });
+}
+
+#[test]
+fn should_remove_pair_with_last_liquidity() {
+    new_test_ext().execute_with(|| {
+        PriceBand::set(Some(Perbill::from_percent(5)));
+        assert_ok!(AntiMevAmm::cp_swap_currency_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            CpSwap::BasedInput { input_amount: 1_000_000, min_output: 1 },
+            System::block_number().saturating_add(1),
+        ));
+        assert_ok!(AntiMevAmm::set_impact_threshold(
+            RuntimeOrigin::root(),
+            ASSET_A,
+            Some(Perbill::from_percent(2))
+        ));
+        assert!(AntiMevAmm::reference_prices(ASSET_A).is_some());
+
+        assert_ok!(AntiMevAmm::remove_liquidity(
+            RuntimeOrigin::signed(ACCOUNT_ALICE),
+            ASSET_A,
+            INIT_LIQUIDITY,
+            1,
+            1,
+            System::block_number().saturating_add(1)
+        ));
+        assert!(AntiMevAmm::pairs(ASSET_A).is_none());
+        assert!(AntiMevAmm::get_pair_currency_queue(&ASSET_A).is_err());
+        assert!(AntiMevAmm::reference_prices(ASSET_A).is_none());
+        assert!(AntiMevAmm::impact_thresholds(ASSET_A).is_none());
+
+        // the pair comes back with a new liquidity token
+        assert_noop!(
+            AntiMevAmm::create_pair(
+                RuntimeOrigin::signed(ACCOUNT_ALICE),
+                ASSET_A,
+                LIQ_TOKEN_A,
+                INIT_LIQUIDITY,
+                INIT_LIQUIDITY
+            ),
+            Error::<TestRuntime>::TokenIdAlreadyInUse
+        );
+        assert_ok!(AntiMevAmm::create_pair(
+            RuntimeOrigin::signed(ACCOUNT_ALICE),
+            ASSET_A,
+            LIQ_TOKEN_B + 1,
+            INIT_LIQUIDITY,
+            INIT_LIQUIDITY
+        ));
+    });
+}
+
+fn queue_full_batch(asset_id: u32) {
+    for buyer in [ACCOUNT_BOB, ACCOUNT_ATTACKER] {
+        assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+            RuntimeOrigin::signed(buyer),
+            asset_id,
+            100,
+            1,
+            System::block_number().saturating_add(1)
+        ));
+    }
+    for seller in [ACCOUNT_ALICE, ACCOUNT_ATTACKER] {
+        assert_ok!(AntiMevAmm::add_swap_asset_for_currency(
+            RuntimeOrigin::signed(seller),
+            asset_id,
+            100,
+            1,
+            System::block_number().saturating_add(1)
+        ));
+    }
+}
+
+/// Pay out every settlement claim.
+fn claim_all() {
+    for (asset_id, intent_id, _) in Claims::<TestRuntime>::iter().collect::<Vec<_>>() {
+        assert_ok!(AntiMevAmm::claim(RuntimeOrigin::signed(ACCOUNT_ERWIN), asset_id, intent_id));
+    }
+}
+
+fn is_queue_empty(asset_id: u32) -> bool {
+    AntiMevAmm::get_pair_currency_queue(&asset_id).expect("pair exists").is_empty()
+        && AntiMevAmm::get_pair_asset_queue(&asset_id).expect("pair exists").is_empty()
+}
+
+#[test]
+fn should_settle_full_queue_on_idle() {
+    new_test_ext().execute_with(|| {
+        queue_full_batch(ASSET_A);
+        assert!(!is_queue_empty(ASSET_A));
+
+        AntiMevAmm::on_idle(System::block_number(), Weight::MAX);
+        assert!(is_queue_empty(ASSET_A));
+        assert!(AntiMevAmm::epoch_start(ASSET_A).is_none());
+        assert!(AntiMevAmm::settlement_cursor().is_none());
+    });
+}
+
+#[test]
+fn should_settle_waiting_queue_after_epoch_length() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            100,
+            1,
+            System::block_number().saturating_add(1)
+        ));
+        assert_ok!(AntiMevAmm::add_swap_asset_for_currency(
+            RuntimeOrigin::signed(ACCOUNT_ALICE),
+            ASSET_A,
+            100,
+            1,
+            System::block_number().saturating_add(1)
+        ));
+        assert_eq!(AntiMevAmm::epoch_start(ASSET_A), Some(1));
+
+        // the queue is not full yet
+        AntiMevAmm::on_idle(System::block_number(), Weight::MAX);
+        assert!(!is_queue_empty(ASSET_A));
+        assert_noop!(
+            AntiMevAmm::settle_and_distribute(RuntimeOrigin::signed(ACCOUNT_BOB), ASSET_A),
+            Error::<TestRuntime>::QueueTooSmall
+        );
+
+        System::set_block_number(1 + <<TestRuntime as Config>::EpochLength as Get<u64>>::get());
+        AntiMevAmm::on_idle(System::block_number(), Weight::MAX);
+        assert!(is_queue_empty(ASSET_A));
+    });
+}
+
+#[test]
+fn should_resume_settlement_in_next_block_when_out_of_weight() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(AntiMevAmm::create_pair(
+            RuntimeOrigin::signed(ACCOUNT_ALICE),
+            ASSET_B,
+            LIQ_TOKEN_B,
+            INIT_LIQUIDITY,
+            INIT_LIQUIDITY
+        ));
+        queue_full_batch(ASSET_A);
+        queue_full_batch(ASSET_B);
+
+        // only enough weight for a single settlement
+        let settle_weight = AntiMevAmm::settle_weight();
+        AntiMevAmm::on_idle(System::block_number(), settle_weight);
+        let settled = if is_queue_empty(ASSET_A) { ASSET_A } else { ASSET_B };
+        assert!(is_queue_empty(settled));
+        assert!(!is_queue_empty(if settled == ASSET_A { ASSET_B } else { ASSET_A }));
+        assert_eq!(AntiMevAmm::settlement_cursor(), Some(settled));
+
+        System::set_block_number(2);
+        AntiMevAmm::on_idle(System::block_number(), settle_weight);
+        assert!(is_queue_empty(ASSET_A));
+        assert!(is_queue_empty(ASSET_B));
+    });
+}
+
+#[test]
+fn should_queue_revealed_swap_intent() {
+    new_test_ext().execute_with(|| {
+        let salt = [7u8; 32];
+        let commitment =
+            AntiMevAmm::commitment_of(&ASSET_A, SwapDirection::CurrencyToAsset, 100, 1, 10, &salt);
+        assert_ok!(AntiMevAmm::commit_swap_intent(RuntimeOrigin::signed(ACCOUNT_BOB), commitment));
+        assert_eq!(Balances::reserved_balance(ACCOUNT_BOB), 1_000);
+        assert!(AntiMevAmm::get_pair_currency_queue(&ASSET_A).expect("pair exists").is_empty());
+
+        // the reveal window starts in a later block
+        assert_noop!(
+            AntiMevAmm::reveal_swap_intent(
+                RuntimeOrigin::signed(ACCOUNT_BOB),
+                ASSET_A,
+                SwapDirection::CurrencyToAsset,
+                100,
+                1,
+                10,
+                salt
+            ),
+            Error::<TestRuntime>::RevealTooEarly
+        );
+        System::set_block_number(2);
+
+        // the revealed intent must match the commitment
+        assert_noop!(
+            AntiMevAmm::reveal_swap_intent(
+                RuntimeOrigin::signed(ACCOUNT_BOB),
+                ASSET_A,
+                SwapDirection::CurrencyToAsset,
+                200,
+                1,
+                10,
+                salt
+            ),
+            Error::<TestRuntime>::CommitmentNotFound
+        );
+        assert_ok!(AntiMevAmm::reveal_swap_intent(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            SwapDirection::CurrencyToAsset,
+            100,
+            1,
+            10,
+            salt
+        ));
+        assert_eq!(Balances::reserved_balance(ACCOUNT_BOB), 0);
+        assert_eq!(AntiMevAmm::get_pair_currency_queue(&ASSET_A).expect("pair exists"), vec![0]);
+        assert_eq!(
+            AntiMevAmm::currency_intents(0),
+            Some(Intent {
+                owner: ACCOUNT_BOB,
+                swap: AntiMevSwap::BasedInput { input_amount: 100, min_output: 1 },
+                deadline: 10,
+                queued_at: System::block_number(),
+            })
+        );
+    });
+}
+
+#[test]
+fn should_forfeit_deposit_of_unrevealed_commitment() {
+    new_test_ext().execute_with(|| {
+        let salt = [7u8; 32];
+        let commitment =
+            AntiMevAmm::commitment_of(&ASSET_A, SwapDirection::AssetToCurrency, 100, 1, 10, &salt);
+        let bob_balance = Balances::total_balance(&ACCOUNT_BOB);
+        assert_ok!(AntiMevAmm::commit_swap_intent(RuntimeOrigin::signed(ACCOUNT_BOB), commitment));
+        assert_noop!(
+            AntiMevAmm::commit_swap_intent(RuntimeOrigin::signed(ACCOUNT_BOB), commitment),
+            Error::<TestRuntime>::CommitmentAlreadyExists
+        );
+
+        // the commitment can only be cleared once the reveal window is over
+        System::set_block_number(6);
+        assert_noop!(
+            AntiMevAmm::clear_expired_commitment(
+                RuntimeOrigin::signed(ACCOUNT_CHARLIE),
+                ACCOUNT_BOB,
+                commitment
+            ),
+            Error::<TestRuntime>::RevealPeriodNotOver
+        );
+        System::set_block_number(7);
+        assert_noop!(
+            AntiMevAmm::reveal_swap_intent(
+                RuntimeOrigin::signed(ACCOUNT_BOB),
+                ASSET_A,
+                SwapDirection::AssetToCurrency,
+                100,
+                1,
+                10,
+                salt
+            ),
+            Error::<TestRuntime>::RevealPeriodOver
+        );
+        assert_ok!(AntiMevAmm::clear_expired_commitment(
+            RuntimeOrigin::signed(ACCOUNT_CHARLIE),
+            ACCOUNT_BOB,
+            commitment
+        ));
+        assert!(AntiMevAmm::commitments(ACCOUNT_BOB, commitment).is_none());
+        assert_eq!(Balances::reserved_balance(ACCOUNT_BOB), 0);
+        assert_eq!(Balances::total_balance(&ACCOUNT_BOB), bob_balance - 500);
+    });
+}
+
+#[test]
+fn should_refund_intent_when_min_output_is_not_met() {
+    new_test_ext().execute_with(|| {
+        let bob_currency_before = Balances::free_balance(ACCOUNT_BOB);
+        let bob_asset_before = <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_BOB);
+        let attacker_asset_before = <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_ATTACKER);
+
+        // Bob asks for more than the batch can pay
+        assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            100,
+            1_000,
+            System::block_number().saturating_add(1)
+        ));
+        assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_ATTACKER),
+            ASSET_A,
+            100,
+            1,
+            System::block_number().saturating_add(1)
+        ));
+        for seller in [ACCOUNT_ALICE, ACCOUNT_ATTACKER] {
+            assert_ok!(AntiMevAmm::add_swap_asset_for_currency(
+                RuntimeOrigin::signed(seller),
+                ASSET_A,
+                100,
+                1,
+                System::block_number().saturating_add(1)
+            ));
+        }
+
+        assert_ok!(AntiMevAmm::settle_and_distribute(
+            RuntimeOrigin::signed(ACCOUNT_CHARLIE),
+            ASSET_A,
+        ));
+        claim_all();
+        System::assert_has_event(
+            Event::<TestRuntime>::RefundedSwapCurrencyForAsset(ASSET_A, 0, ACCOUNT_BOB, 100).into()
+        );
+        assert_eq!(Balances::free_balance(ACCOUNT_BOB), bob_currency_before);
+        assert_eq!(<TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_BOB), bob_asset_before);
+        assert!(<TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_ATTACKER) > attacker_asset_before - 100);
+        assert!(is_queue_empty(ASSET_A));
+        assert!(AntiMevAmm::currency_intents(0).is_none());
+    });
+}
+
+#[test]
+fn should_refund_intent_past_its_deadline() {
+    new_test_ext().execute_with(|| {
+        let bob_currency_before = Balances::free_balance(ACCOUNT_BOB);
+        let bob_asset_before = <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_BOB);
+        let epoch_end = 1 + <<TestRuntime as Config>::EpochLength as Get<u64>>::get();
+
+        // Bob's intent expires before the epoch ends
+        assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            1_000_000,
+            1,
+            System::block_number().saturating_add(1)
+        ));
+        for buyer in [ACCOUNT_CHARLIE, ACCOUNT_DAVE] {
+            assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+                RuntimeOrigin::signed(buyer),
+                ASSET_A,
+                1_000_000,
+                1,
+                epoch_end
+            ));
+        }
+        let charlie_asset_before = <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_CHARLIE);
+        let dave_asset_before = <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_DAVE);
+
+        // the expired intent doesn't take a place in the batch
+        System::set_block_number(epoch_end);
+        assert_ok!(AntiMevAmm::settle_and_distribute(
+            RuntimeOrigin::signed(ACCOUNT_ERWIN),
+            ASSET_A,
+        ));
+        claim_all();
+        System::assert_has_event(
+            Event::<TestRuntime>::ExpiredSwapCurrencyForAsset(ASSET_A, 0, ACCOUNT_BOB, 1_000_000).into()
+        );
+        assert_eq!(Balances::free_balance(ACCOUNT_BOB), bob_currency_before);
+        assert_eq!(<TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_BOB), bob_asset_before);
+        assert!(<TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_CHARLIE) > charlie_asset_before);
+        assert!(<TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_DAVE) > dave_asset_before);
+        assert!(is_queue_empty(ASSET_A));
+        assert!(AntiMevAmm::currency_intents(0).is_none());
+        assert!(AntiMevAmm::epoch_start(ASSET_A).is_none());
+    });
+}
+
+#[test]
+fn should_cap_expired_intents_per_batch() {
+    new_test_ext().execute_with(|| {
+        let epoch_end = 1 + <<TestRuntime as Config>::EpochLength as Get<u64>>::get();
+        let deadline = System::block_number().saturating_add(1);
+        for buyer in [ACCOUNT_BOB, ACCOUNT_CHARLIE, ACCOUNT_DAVE] {
+            assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+                RuntimeOrigin::signed(buyer),
+                ASSET_A,
+                1_000_000,
+                1,
+                deadline
+            ));
+        }
+        assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_ERWIN),
+            ASSET_A,
+            1_000_000,
+            1,
+            epoch_end
+        ));
+
+        // a batch refunds at most `MinQueueAmount` expired intents
+        System::set_block_number(epoch_end);
+        let (live, expired) = AntiMevAmm::batch_currency_intents(&ASSET_A, epoch_end);
+        assert!(live.is_empty());
+        assert_eq!(expired.iter().map(|(intent_id, _)| *intent_id).collect::<Vec<_>>(), vec![0, 1]);
+        assert_ok!(AntiMevAmm::settle_and_distribute(
+            RuntimeOrigin::signed(ACCOUNT_ERWIN),
+            ASSET_A,
+        ));
+        assert_eq!(AntiMevAmm::get_pair_currency_queue(&ASSET_A).ok(), Some(vec![2, 3]));
+
+        // the next batch refunds the last one and settles the live intent
+        assert_ok!(AntiMevAmm::settle_and_distribute(
+            RuntimeOrigin::signed(ACCOUNT_ERWIN),
+            ASSET_A,
+        ));
+        System::assert_has_event(
+            Event::<TestRuntime>::ExpiredSwapCurrencyForAsset(ASSET_A, 2, ACCOUNT_DAVE, 1_000_000).into()
+        );
+        assert!(AntiMevAmm::claims(ASSET_A, 3).is_some());
+        assert!(is_queue_empty(ASSET_A));
+    });
+}
+
+#[test]
+fn should_cancel_queued_swap_intent() {
+    new_test_ext().execute_with(|| {
+        let bob_currency_before = Balances::free_balance(ACCOUNT_BOB);
+        assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            100,
+            1,
+            System::block_number().saturating_add(1)
+        ));
+        assert_noop!(
+            AntiMevAmm::cancel_swap_intent(
+                RuntimeOrigin::signed(ACCOUNT_BOB),
+                ASSET_A,
+                SwapDirection::AssetToCurrency,
+            ),
+            Error::<TestRuntime>::SwapIntentNotFound
+        );
+
+        assert_ok!(AntiMevAmm::cancel_swap_intent(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            SwapDirection::CurrencyToAsset,
+        ));
+        System::assert_last_event(
+            Event::<TestRuntime>::CancelledSwapCurrencyForAsset(ASSET_A, 0, ACCOUNT_BOB, 100).into()
+        );
+        assert_eq!(Balances::free_balance(ACCOUNT_BOB), bob_currency_before);
+        assert!(AntiMevAmm::currency_intents(0).is_none());
+        assert!(is_queue_empty(ASSET_A));
+        assert!(AntiMevAmm::epoch_start(ASSET_A).is_none());
+    });
+}
+
+#[test]
+fn should_settle_queue_past_cancelled_intents() {
+    new_test_ext().execute_with(|| {
+        let epoch_end = 1 + <<TestRuntime as Config>::EpochLength as Get<u64>>::get();
+        for buyer in [ACCOUNT_BOB, ACCOUNT_CHARLIE, ACCOUNT_DAVE, ACCOUNT_ERWIN] {
+            assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+                RuntimeOrigin::signed(buyer),
+                ASSET_A,
+                1_000_000,
+                1,
+                epoch_end
+            ));
+        }
+
+        // a cancelled intent leaves an empty slot, unless it ends the queue
+        for canceller in [ACCOUNT_CHARLIE, ACCOUNT_ERWIN] {
+            assert_ok!(AntiMevAmm::cancel_swap_intent(
+                RuntimeOrigin::signed(canceller),
+                ASSET_A,
+                SwapDirection::CurrencyToAsset,
+            ));
+        }
+        assert_eq!(AntiMevAmm::queue_len(&ASSET_A, SwapDirection::CurrencyToAsset), 3);
+        assert_eq!(AntiMevAmm::get_pair_currency_queue(&ASSET_A).ok(), Some(vec![0, 2]));
+
+        // the batch skips the empty slot and is popped past it
+        System::set_block_number(epoch_end);
+        assert_ok!(AntiMevAmm::settle_and_distribute(
+            RuntimeOrigin::signed(ACCOUNT_ERWIN),
+            ASSET_A,
+        ));
+        assert!(AntiMevAmm::claims(ASSET_A, 0).is_some());
+        assert!(AntiMevAmm::claims(ASSET_A, 2).is_some());
+        assert!(is_queue_empty(ASSET_A));
+        assert_eq!(AntiMevAmm::queue_index(ASSET_A, SwapDirection::CurrencyToAsset), QueueIndex::default());
+    });
+}
+
+#[test]
+fn should_not_cancel_swap_intent_once_settlement_started() {
+    new_test_ext().execute_with(|| {
+        queue_full_batch(ASSET_A);
+
+        assert_noop!(
+            AntiMevAmm::cancel_swap_intent(
+                RuntimeOrigin::signed(ACCOUNT_ALICE),
+                ASSET_A,
+                SwapDirection::AssetToCurrency,
+            ),
+            Error::<TestRuntime>::SettlementStarted
+        );
+    });
+}
+
+#[test]
+fn should_reject_intent_when_queue_is_full() {
+    new_test_ext().execute_with(|| {
+        let max_queue_length = <<TestRuntime as Config>::MaxQueueLength as Get<u32>>::get();
+        for _ in 0..max_queue_length {
+            assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+                RuntimeOrigin::signed(ACCOUNT_BOB),
+                ASSET_A,
+                100,
+                1,
+                System::block_number().saturating_add(1)
+            ));
+        }
+        assert_eq!(AntiMevAmm::queue_index(ASSET_A, SwapDirection::CurrencyToAsset).len(), max_queue_length);
+        assert_noop!(
+            AntiMevAmm::add_swap_currency_for_asset(
+                RuntimeOrigin::signed(ACCOUNT_BOB),
+                ASSET_A,
+                100,
+                1,
+                System::block_number().saturating_add(1)
+            ),
+            Error::<TestRuntime>::QueueFull
+        );
+
+        // cancelling frees the slots again
+        assert_ok!(AntiMevAmm::cancel_swap_intent(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            SwapDirection::CurrencyToAsset,
+        ));
+        assert!(AntiMevAmm::queue_index(ASSET_A, SwapDirection::CurrencyToAsset).is_empty());
+        assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            100,
+            1,
+            System::block_number().saturating_add(1)
+        ));
+    });
+}
+
+#[test]
+fn should_pay_each_intent_once_and_carry_over_the_rest() {
+    new_test_ext().execute_with(|| {
+        // Bob submits twice, the attacker's intent is beyond the batch
+        for buyer in [ACCOUNT_BOB, ACCOUNT_BOB, ACCOUNT_ATTACKER] {
+            assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+                RuntimeOrigin::signed(buyer),
+                ASSET_A,
+                100,
+                1,
+                System::block_number().saturating_add(1)
+            ));
+        }
+        for seller in [ACCOUNT_ALICE, ACCOUNT_ATTACKER] {
+            assert_ok!(AntiMevAmm::add_swap_asset_for_currency(
+                RuntimeOrigin::signed(seller),
+                ASSET_A,
+                100,
+                1,
+                System::block_number().saturating_add(1)
+            ));
+        }
+        let bob_asset_before = <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_BOB);
+
+        assert_ok!(AntiMevAmm::settle_and_distribute(
+            RuntimeOrigin::signed(ACCOUNT_CHARLIE),
+            ASSET_A,
+        ));
+        claim_all();
+        let bob_payouts: Vec<u128> = System::events()
+            .into_iter()
+            .filter_map(|record| match record.event {
+                RuntimeEvent::AntiMevAmm(Event::SwappedCurrencyForAsset(_, buyer, _, _, amount_out))
+                    if buyer == ACCOUNT_BOB => Some(amount_out),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(bob_payouts.len(), 2);
+        assert_eq!(
+            <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_BOB),
+            bob_asset_before + bob_payouts.iter().sum::<u128>()
+        );
+
+        // the attacker's intent waits for the next batch
+        assert_eq!(AntiMevAmm::get_pair_currency_queue(&ASSET_A).expect("pair exists"), vec![2]);
+        assert!(AntiMevAmm::get_pair_asset_queue(&ASSET_A).expect("pair exists").is_empty());
+        assert!(AntiMevAmm::currency_intents(0).is_none());
+        assert!(AntiMevAmm::currency_intents(1).is_none());
+        assert!(AntiMevAmm::currency_intents(2).is_some());
+        assert_eq!(AntiMevAmm::epoch_start(ASSET_A), Some(1));
+    });
+}
+
+#[test]
+fn should_settle_one_sided_queue_at_uniform_price() {
+    new_test_ext().execute_with(|| {
+        let bob_asset_before = <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_BOB);
+        let attacker_asset_before = <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_ATTACKER);
+        // only buyers, and the attacker buys twice as much
+        assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            1_000_000,
+            1,
+            System::block_number().saturating_add(100)
+        ));
+        assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_ATTACKER),
+            ASSET_A,
+            2_000_000,
+            1,
+            System::block_number().saturating_add(100)
+        ));
+        assert_noop!(
+            AntiMevAmm::settle_and_distribute(RuntimeOrigin::signed(ACCOUNT_CHARLIE), ASSET_A),
+            Error::<TestRuntime>::QueueTooSmall
+        );
+
+        System::set_block_number(1 + <<TestRuntime as Config>::EpochLength as Get<u64>>::get());
+        assert_ok!(AntiMevAmm::settle_and_distribute(
+            RuntimeOrigin::signed(ACCOUNT_CHARLIE),
+            ASSET_A,
+        ));
+        claim_all();
+        let bob_out = <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_BOB) - bob_asset_before;
+        let attacker_out =
+            <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_ATTACKER) - attacker_asset_before;
+        assert!(bob_out > 0);
+        assert!(attacker_out.abs_diff(2 * bob_out) <= 1);
+        assert!(is_queue_empty(ASSET_A));
+        assert!(AntiMevAmm::epoch_start(ASSET_A).is_none());
+    });
+}
+
+#[test]
+fn should_record_claims_instead_of_paying_out() {
+    new_test_ext().execute_with(|| {
+        let bob_asset_before = <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_BOB);
+        assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            1_000_000,
+            1,
+            System::block_number().saturating_add(100)
+        ));
+        assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            1_000_000,
+            1,
+            System::block_number().saturating_add(100)
+        ));
+
+        System::set_block_number(1 + <<TestRuntime as Config>::EpochLength as Get<u64>>::get());
+        assert_ok!(AntiMevAmm::settle_and_distribute(
+            RuntimeOrigin::signed(ACCOUNT_CHARLIE),
+            ASSET_A,
+        ));
+        // the settlement only records what each intent is owed
+        assert_eq!(<TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_BOB), bob_asset_before);
+        let claim = AntiMevAmm::claims(ASSET_A, 0).expect("claim recorded");
+        assert_eq!(claim.owner, ACCOUNT_BOB);
+        assert_eq!(claim.currency, 0);
+        assert!(claim.asset > 0);
+
+        // anyone can pay the claim out to its owner, once
+        assert_ok!(AntiMevAmm::claim(RuntimeOrigin::signed(ACCOUNT_DAVE), ASSET_A, 0));
+        assert_eq!(
+            <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_BOB),
+            bob_asset_before + claim.asset
+        );
+        System::assert_last_event(
+            Event::<TestRuntime>::Claimed(ASSET_A, 0, ACCOUNT_BOB, 0, claim.asset).into()
+        );
+        assert_noop!(
+            AntiMevAmm::claim(RuntimeOrigin::signed(ACCOUNT_DAVE), ASSET_A, 0),
+            Error::<TestRuntime>::ClaimNotFound
+        );
+        assert!(AntiMevAmm::claims(ASSET_A, 1).is_some());
+    });
+}
+
+#[test]
+fn should_preview_settlement_without_settling() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            1_000_000,
+            1,
+            System::block_number().saturating_add(100)
+        ));
+        // can't be met, it is refunded
+        assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_ATTACKER),
+            ASSET_A,
+            1_000_000,
+            2_000_000,
+            System::block_number().saturating_add(100)
+        ));
+        assert_ok!(AntiMevAmm::add_swap_asset_for_currency(
+            RuntimeOrigin::signed(ACCOUNT_ALICE),
+            ASSET_A,
+            3_000_000,
+            1,
+            System::block_number().saturating_add(100)
+        ));
+
+        System::set_block_number(1 + <<TestRuntime as Config>::EpochLength as Get<u64>>::get());
+        let preview = AntiMevAmm::preview_settlement(&ASSET_A).expect("pair exists");
+        assert_eq!(preview.payouts.len(), 3);
+        assert!(preview.buy_price > FixedU128::zero());
+        assert!(preview.sell_price > FixedU128::zero());
+        // the dry run leaves the queues as they are
+        assert_eq!(AntiMevAmm::queue_len(&ASSET_A, SwapDirection::CurrencyToAsset), 2);
+        let refunded = preview
+            .payouts
+            .iter()
+            .find(|payout| payout.owner == ACCOUNT_ATTACKER)
+            .expect("intent is in the batch");
+        assert!(!refunded.settled);
+        assert_eq!((refunded.currency, refunded.asset), (1_000_000, 0));
+
+        assert_ok!(AntiMevAmm::settle_and_distribute(
+            RuntimeOrigin::signed(ACCOUNT_CHARLIE),
+            ASSET_A,
+        ));
+        let pair = AntiMevAmm::pairs(ASSET_A).expect("pair exists");
+        assert_eq!(
+            (pair.currency_reserve, pair.token_reserve),
+            (preview.currency_reserve, preview.token_reserve)
+        );
+        for payout in preview.payouts.iter() {
+            let claim = AntiMevAmm::claims(ASSET_A, payout.intent_id).expect("claim recorded");
+            assert_eq!(claim.owner, payout.owner);
+            assert_eq!((claim.currency, claim.asset), (payout.currency, payout.asset));
+        }
+    });
+}
+
+fn signed_intent(
+    signer: u64,
+    direction: SwapDirection,
+    amount_in: u128,
+    nonce: u64,
+) -> SignedIntentOf<TestRuntime> {
+    SignedIntent {
+        signer,
+        asset_id: ASSET_A,
+        direction,
+        amount_in,
+        min_output: 1,
+        deadline: System::block_number().saturating_add(100),
+        nonce,
+    }
+}
+
+fn sign(intent: SignedIntentOf<TestRuntime>) -> (SignedIntentOf<TestRuntime>, TestSignature) {
+    let signature = TestSignature(intent.signer, AntiMevAmm::signed_intent_payload(&intent));
+    (intent, signature)
+}
+
+#[test]
+fn should_queue_signed_intents_submitted_by_relayer() {
+    new_test_ext().execute_with(|| {
+        let bob_currency_before = Balances::free_balance(ACCOUNT_BOB);
+        let attacker_asset_before = <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_ATTACKER);
+        let relayer_currency_before = Balances::free_balance(ACCOUNT_DAVE);
+
+        let buy = sign(signed_intent(ACCOUNT_BOB, SwapDirection::CurrencyToAsset, 1_000_000, 0));
+        let sell = sign(signed_intent(ACCOUNT_ATTACKER, SwapDirection::AssetToCurrency, 2_000_000, 0));
+        // signed by another account than the signer of the intent
+        let forged = signed_intent(ACCOUNT_BOB, SwapDirection::CurrencyToAsset, 5_000_000, 1);
+        let forged = (forged.clone(), TestSignature(ACCOUNT_ATTACKER, AntiMevAmm::signed_intent_payload(&forged)));
+        // signed without the context and the genesis hash
+        let unbound = signed_intent(ACCOUNT_ATTACKER, SwapDirection::AssetToCurrency, 3_000_000, 1);
+        let unbound = (unbound.clone(), TestSignature(ACCOUNT_ATTACKER, unbound.encode()));
+        // replays the nonce of the buy
+        let replayed = buy.clone();
+        assert_ok!(AntiMevAmm::submit_signed_intents(
+            RuntimeOrigin::signed(ACCOUNT_DAVE),
+            vec![buy, sell, forged, unbound, replayed].try_into().expect("within bounds"),
+        ));
+
+        assert_eq!(AntiMevAmm::queue_len(&ASSET_A, SwapDirection::CurrencyToAsset), 1);
+        assert_eq!(AntiMevAmm::queue_len(&ASSET_A, SwapDirection::AssetToCurrency), 1);
+        assert_eq!(AntiMevAmm::signed_intent_nonces(ACCOUNT_BOB), 1);
+        assert_eq!(AntiMevAmm::signed_intent_nonces(ACCOUNT_ATTACKER), 1);
+        // each signer pays its own input
+        assert_eq!(Balances::free_balance(ACCOUNT_BOB), bob_currency_before - 1_000_000);
+        assert_eq!(
+            <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_ATTACKER),
+            attacker_asset_before - 2_000_000
+        );
+        assert_eq!(Balances::free_balance(ACCOUNT_DAVE), relayer_currency_before);
+        System::assert_has_event(
+            Event::<TestRuntime>::RejectedSignedIntent(
+                ACCOUNT_BOB,
+                1,
+                Error::<TestRuntime>::BadSignature.into(),
+            ).into()
+        );
+        System::assert_has_event(
+            Event::<TestRuntime>::RejectedSignedIntent(
+                ACCOUNT_ATTACKER,
+                1,
+                Error::<TestRuntime>::BadSignature.into(),
+            ).into()
+        );
+        System::assert_last_event(
+            Event::<TestRuntime>::RejectedSignedIntent(
+                ACCOUNT_BOB,
+                0,
+                Error::<TestRuntime>::BadNonce.into(),
+            ).into()
+        );
+    });
+}
+
+#[test]
+fn should_register_and_unregister_solver() {
+    new_test_ext().execute_with(|| {
+        let bond = <<TestRuntime as Config>::SolverBond as Get<u128>>::get();
+        assert_noop!(
+            AntiMevAmm::unregister_solver(RuntimeOrigin::signed(ACCOUNT_DAVE)),
+            Error::<TestRuntime>::NotSolver
+        );
+        assert_ok!(AntiMevAmm::register_solver(RuntimeOrigin::signed(ACCOUNT_DAVE)));
+        assert_eq!(Balances::reserved_balance(ACCOUNT_DAVE), bond);
+        assert_eq!(AntiMevAmm::solvers(ACCOUNT_DAVE), Some(bond));
+        assert_noop!(
+            AntiMevAmm::register_solver(RuntimeOrigin::signed(ACCOUNT_DAVE)),
+            Error::<TestRuntime>::AlreadySolver
+        );
+
+        assert_ok!(AntiMevAmm::unregister_solver(RuntimeOrigin::signed(ACCOUNT_DAVE)));
+        assert_eq!(Balances::reserved_balance(ACCOUNT_DAVE), 0);
+        System::assert_last_event(Event::<TestRuntime>::SolverUnregistered(ACCOUNT_DAVE, bond).into());
+    });
+}
+
+#[test]
+fn should_settle_batch_with_best_solver_proposal() {
+    new_test_ext().execute_with(|| {
+        BiddingPeriod::set(5);
+        let fee = <<TestRuntime as Config>::SolverFee as Get<u128>>::get();
+        assert_ok!(Balances::transfer_allow_death(
+            RuntimeOrigin::signed(ACCOUNT_ALICE),
+            <TestRuntime as ConfigHelper>::reward_pot(),
+            1_000 * fee
+        ));
+        assert_ok!(AntiMevAmm::register_solver(RuntimeOrigin::signed(ACCOUNT_DAVE)));
+        // each intent alone clears above its limit, both together at a uniform price don't
+        let pair = AntiMevAmm::pairs(ASSET_A).expect("pair exists");
+        let amount_in = INIT_LIQUIDITY / 100;
+        let alone = AntiMevAmm::cp_get_output_amount(&amount_in, &pair.currency_reserve, &pair.token_reserve)
+            .expect("trade is possible");
+        let min_output = alone - 1_000;
+        for account in [ACCOUNT_BOB, ACCOUNT_ATTACKER] {
+            assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+                RuntimeOrigin::signed(account),
+                ASSET_A,
+                amount_in,
+                min_output,
+                System::block_number().saturating_add(100)
+            ));
+        }
+
+        // the first settlement opens the bidding
+        let epoch_end = 1 + <<TestRuntime as Config>::EpochLength as Get<u64>>::get();
+        System::set_block_number(epoch_end);
+        assert_ok!(AntiMevAmm::settle_and_distribute(RuntimeOrigin::signed(ACCOUNT_CHARLIE), ASSET_A));
+        System::assert_last_event(Event::<TestRuntime>::BiddingOpened(ASSET_A, epoch_end + 5).into());
+        assert_eq!(AntiMevAmm::queue_len(&ASSET_A, SwapDirection::CurrencyToAsset), 2);
+
+        // the batch cleared by the pallet refunds both intents, a solver can fill one
+        let (buys, _) = AntiMevAmm::batch_currency_intents(&ASSET_A, epoch_end);
+        let clearing = AntiMevAmm::clearing_proposal(&pair, &buys, &[]).expect("batch clears");
+        assert!(clearing.buy_fills.is_empty());
+        let fill = |amount_in, amount_out| SettlementProposal {
+            buy_fills: bounded_vec![Fill { intent_id: buys[0].0, amount_in, amount_out }],
+            sell_fills: bounded_vec![],
+            currency_reserve: pair.currency_reserve + amount_in,
+            token_reserve: pair.token_reserve - amount_out,
+        };
+        let proposal = fill(amount_in, alone);
+
+        assert_noop!(
+            AntiMevAmm::submit_settlement(RuntimeOrigin::signed(ACCOUNT_ERWIN), ASSET_A, proposal.clone()),
+            Error::<TestRuntime>::NotSolver
+        );
+        assert_noop!(
+            AntiMevAmm::submit_settlement(RuntimeOrigin::signed(ACCOUNT_DAVE), ASSET_A, clearing.clone()),
+            Error::<TestRuntime>::ProposalNotBetter
+        );
+        let mut invalid = proposal.clone();
+        invalid.buy_fills.try_push(invalid.buy_fills[0].clone()).expect("within bound");
+        assert_noop!(
+            AntiMevAmm::submit_settlement(RuntimeOrigin::signed(ACCOUNT_DAVE), ASSET_A, invalid.clone()),
+            Error::<TestRuntime>::InvalidFill
+        );
+        // no more fills than intents in the batch
+        invalid.buy_fills.try_push(invalid.buy_fills[0].clone()).expect("within bound");
+        assert_noop!(
+            AntiMevAmm::submit_settlement(RuntimeOrigin::signed(ACCOUNT_DAVE), ASSET_A, invalid),
+            Error::<TestRuntime>::ProposalTooLarge
+        );
+        // an exact input intent trades its whole input
+        assert_noop!(
+            AntiMevAmm::submit_settlement(RuntimeOrigin::signed(ACCOUNT_DAVE), ASSET_A, fill(0, min_output)),
+            Error::<TestRuntime>::InvalidFill
+        );
+        assert_noop!(
+            AntiMevAmm::submit_settlement(RuntimeOrigin::signed(ACCOUNT_DAVE), ASSET_A, fill(amount_in, 0)),
+            Error::<TestRuntime>::FillBelowLimit
+        );
+        let mut invalid = proposal.clone();
+        invalid.token_reserve += 1_000;
+        assert_noop!(
+            AntiMevAmm::submit_settlement(RuntimeOrigin::signed(ACCOUNT_DAVE), ASSET_A, invalid),
+            Error::<TestRuntime>::ReservesMismatch
+        );
+        // the fill has to pay the provider fee
+        let without_fee = pair.token_reserve * amount_in / (pair.currency_reserve + amount_in);
+        assert_noop!(
+            AntiMevAmm::submit_settlement(
+                RuntimeOrigin::signed(ACCOUNT_DAVE),
+                ASSET_A,
+                fill(amount_in, without_fee)
+            ),
+            Error::<TestRuntime>::InvariantBroken
+        );
+        assert_noop!(
+            AntiMevAmm::submit_settlement(RuntimeOrigin::signed(ACCOUNT_DAVE), ASSET_A, fill(amount_in, alone + 1)),
+            Error::<TestRuntime>::InvariantBroken
+        );
+        assert_ok!(AntiMevAmm::submit_settlement(
+            RuntimeOrigin::signed(ACCOUNT_DAVE),
+            ASSET_A,
+            proposal.clone()
+        ));
+        let surplus = AntiMevAmm::best_bids(ASSET_A).expect("bid is kept").surplus;
+
+        // the batch settles once the bidding is over
+        assert_noop!(
+            AntiMevAmm::settle_and_distribute(RuntimeOrigin::signed(ACCOUNT_CHARLIE), ASSET_A),
+            Error::<TestRuntime>::BiddingOpen
+        );
+        System::set_block_number(epoch_end + 5);
+        let dave_before = Balances::free_balance(ACCOUNT_DAVE);
+        assert_ok!(AntiMevAmm::settle_and_distribute(RuntimeOrigin::signed(ACCOUNT_CHARLIE), ASSET_A));
+        System::assert_has_event(
+            Event::<TestRuntime>::SettledByProposal(ASSET_A, ACCOUNT_DAVE, surplus).into()
+        );
+        System::assert_has_event(Event::<TestRuntime>::SolverPaid(ACCOUNT_DAVE, fee).into());
+        assert_eq!(Balances::free_balance(ACCOUNT_DAVE), dave_before + fee);
+        assert!(is_queue_empty(ASSET_A));
+        assert!(AntiMevAmm::bidding_end(ASSET_A).is_none());
+        assert!(AntiMevAmm::best_bids(ASSET_A).is_none());
+
+        let settled_pair = AntiMevAmm::pairs(ASSET_A).expect("pair exists");
+        assert_eq!(
+            (settled_pair.currency_reserve, settled_pair.token_reserve),
+            (proposal.currency_reserve, proposal.token_reserve)
+        );
+        for fill in proposal.buy_fills.iter() {
+            let claim = AntiMevAmm::claims(ASSET_A, fill.intent_id).expect("claim recorded");
+            assert_eq!(claim.asset, fill.amount_out);
+        }
+    });
+}
+
+#[test]
+fn should_settle_only_the_batch_bid_on() {
+    new_test_ext().execute_with(|| {
+        BiddingPeriod::set(5);
+        assert_ok!(AntiMevAmm::register_solver(RuntimeOrigin::signed(ACCOUNT_DAVE)));
+        let pair = AntiMevAmm::pairs(ASSET_A).expect("pair exists");
+        let amount_in = INIT_LIQUIDITY / 100;
+        let alone = AntiMevAmm::cp_get_output_amount(&amount_in, &pair.currency_reserve, &pair.token_reserve)
+            .expect("trade is possible");
+        for account in [ACCOUNT_BOB, ACCOUNT_ATTACKER] {
+            assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+                RuntimeOrigin::signed(account),
+                ASSET_A,
+                amount_in,
+                alone - 1_000,
+                System::block_number().saturating_add(100)
+            ));
+        }
+        let epoch_end = 1 + <<TestRuntime as Config>::EpochLength as Get<u64>>::get();
+        System::set_block_number(epoch_end);
+        assert_ok!(AntiMevAmm::settle_and_distribute(RuntimeOrigin::signed(ACCOUNT_CHARLIE), ASSET_A));
+        let (buys, _) = AntiMevAmm::batch_currency_intents(&ASSET_A, epoch_end);
+        let proposal = SettlementProposal {
+            buy_fills: bounded_vec![Fill { intent_id: buys[0].0, amount_in, amount_out: alone }],
+            sell_fills: bounded_vec![],
+            currency_reserve: pair.currency_reserve + amount_in,
+            token_reserve: pair.token_reserve - alone,
+        };
+        assert_ok!(AntiMevAmm::submit_settlement(RuntimeOrigin::signed(ACCOUNT_DAVE), ASSET_A, proposal));
+
+        // an intent queued during the bidding isn't part of the batch
+        System::set_block_number(epoch_end + 1);
+        assert_ok!(AntiMevAmm::add_swap_asset_for_currency(
+            RuntimeOrigin::signed(ACCOUNT_ALICE),
+            ASSET_A,
+            1_000_000,
+            1,
+            System::block_number().saturating_add(100)
+        ));
+        let (sells, _) = AntiMevAmm::batch_asset_intents(&ASSET_A, epoch_end + 1);
+        assert!(sells.is_empty());
+
+        System::set_block_number(epoch_end + 5);
+        assert_ok!(AntiMevAmm::settle_and_distribute(RuntimeOrigin::signed(ACCOUNT_CHARLIE), ASSET_A));
+        assert!(!System::events().iter().any(|record| matches!(
+            record.event,
+            RuntimeEvent::AntiMevAmm(Event::RefundedSwapAssetForCurrency(..))
+        )));
+        assert_eq!(AntiMevAmm::queue_len(&ASSET_A, SwapDirection::CurrencyToAsset), 0);
+        assert_eq!(AntiMevAmm::queue_len(&ASSET_A, SwapDirection::AssetToCurrency), 1);
+        assert_eq!(AntiMevAmm::pending_inputs(ASSET_A), (0, 1_000_000));
+        assert!(AntiMevAmm::bidding_intents(ASSET_A).is_none());
+    });
+}
+
+#[test]
+fn should_distribute_batch_in_random_order() {
+    // the same seed always gives the same permutation
+    let mut items: Vec<u32> = (0..8).collect();
+    let mut again = items.clone();
+    AntiMevAmm::shuffle(&mut items, H256::repeat_byte(1));
+    AntiMevAmm::shuffle(&mut again, H256::repeat_byte(1));
+    assert_eq!(items, again);
+    let mut sorted = items.clone();
+    sorted.sort();
+    assert_eq!(sorted, (0..8).collect::<Vec<_>>());
+
+    let distribution_order = |seed: H256| {
+        new_test_ext().execute_with(|| {
+            RandomSeed::set(seed);
+            for account in [ACCOUNT_BOB, ACCOUNT_ATTACKER] {
+                assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+                    RuntimeOrigin::signed(account),
+                    ASSET_A,
+                    1_000_000,
+                    1,
+                    System::block_number().saturating_add(100)
+                ));
+            }
+            System::set_block_number(1 + <<TestRuntime as Config>::EpochLength as Get<u64>>::get());
+            assert_ok!(AntiMevAmm::settle_and_distribute(
+                RuntimeOrigin::signed(ACCOUNT_CHARLIE),
+                ASSET_A,
+            ));
+            let order: Vec<_> = System::events()
+                .into_iter()
+                .filter_map(|record| match record.event {
+                    RuntimeEvent::AntiMevAmm(Event::SwappedCurrencyForAsset(_, account, _, _, amount_out)) => {
+                        Some((account, amount_out))
+                    }
+                    _ => None,
+                })
+                .collect();
+            let mut expected = vec![ACCOUNT_BOB, ACCOUNT_ATTACKER];
+            AntiMevAmm::shuffle(
+                &mut expected,
+                AntiMevAmm::batch_seed(&ASSET_A, SwapDirection::CurrencyToAsset).0,
+            );
+            assert_eq!(order.iter().map(|(account, _)| *account).collect::<Vec<_>>(), expected);
+            order
+        })
+    };
+
+    // seeds putting either intent first in line
+    let seed_first = |account| {
+        (0u8..=255)
+            .map(H256::repeat_byte)
+            .find(|seed| distribution_order(*seed)[0].0 == account)
+            .expect("some seed puts the intent first")
+    };
+    let shuffled = distribution_order(seed_first(ACCOUNT_ATTACKER));
+    let queued = distribution_order(seed_first(ACCOUNT_BOB));
+    // the order changes nothing but the order
+    assert_eq!(shuffled[0].1, queued[1].1);
+    assert_eq!(shuffled[1].1, queued[0].1);
+}
+
+#[test]
+fn should_leave_intents_queued_once_the_seed_is_known_to_the_next_batch() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            1_000_000,
+            1,
+            System::block_number().saturating_add(100)
+        ));
+        System::set_block_number(5);
+        assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_ATTACKER),
+            ASSET_A,
+            1_000_000,
+            1,
+            System::block_number().saturating_add(100)
+        ));
+        System::set_block_number(1 + <<TestRuntime as Config>::EpochLength as Get<u64>>::get());
+
+        // nothing was queued before the seed was known
+        RandomKnownSince::set(Some(1));
+        assert_noop!(
+            AntiMevAmm::settle_and_distribute(RuntimeOrigin::signed(ACCOUNT_CHARLIE), ASSET_A),
+            Error::<TestRuntime>::QueueTooSmall
+        );
+
+        // the intent queued once the seed was known waits for the next batch
+        RandomKnownSince::set(Some(5));
+        assert_ok!(AntiMevAmm::settle_and_distribute(RuntimeOrigin::signed(ACCOUNT_CHARLIE), ASSET_A));
+        assert!(AntiMevAmm::currency_intents(0).is_none());
+        assert_eq!(AntiMevAmm::get_pair_currency_queue(&ASSET_A).expect("pair exists"), vec![1]);
+    });
+}
+
+#[test]
+fn should_settle_without_ordering_bias() {
+    new_test_ext().execute_with(|| {
+        // a symmetric batch on a symmetric pair must favour neither direction
+        let pair = AntiMevAmm::pairs(ASSET_A).expect("pair exists");
+        let amount = INIT_LIQUIDITY;
+        let simulation = AntiMevAmm::simulate_settlement(&pair, amount, amount).expect("valid batch");
+
+        let currency_out = pair.currency_reserve + amount - simulation.currency_reserve;
+        let asset_out = pair.token_reserve + amount - simulation.asset_reserve;
+        assert!(currency_out.abs_diff(asset_out) <= 1);
+
+        // stepping through the curve currency first favours the asset sellers
+        let (currency_reserve, asset_reserve) = AntiMevAmm::simulate_fragments(
+            &pair,
+            amount,
+            amount,
+            SwapDirection::CurrencyToAsset,
+        );
+        let currency_out = pair.currency_reserve + amount - currency_reserve;
+        let asset_out = pair.token_reserve + amount - asset_reserve;
+        assert!(currency_out > asset_out + 1);
+    });
+}
+
+#[test]
+fn should_match_fragmented_reference_settlement() {
+    new_test_ext().execute_with(|| {
+        let pair = AntiMevAmm::pairs(ASSET_A).expect("pair exists");
+        let fragment = <<TestRuntime as Config>::Fragment as Get<u32>>::get() as u128;
+        for (currency, asset) in [
+            (1_000_000_000, 1_000_000_000),
+            (1_000_000_000, 0),
+            (0, 1_000_000_000),
+            (2_000_000_000, 1_000_000_000),
+            (1_000_000_000, 3_000_000_000),
+        ] {
+            let simulation = AntiMevAmm::simulate_settlement(&pair, currency, asset).expect("valid batch");
+            let reference =
+                AntiMevAmm::simulate_fragmented_settlement(&pair, currency, asset).expect("valid batch");
+
+            // the reference only drifts by its per fragment rounding
+            let currency_out = pair.currency_reserve + currency - simulation.currency_reserve;
+            let asset_out = pair.token_reserve + asset - simulation.asset_reserve;
+            assert!(
+                simulation.currency_reserve.abs_diff(reference.currency_reserve)
+                    <= currency_out / 10_000 + fragment
+            );
+            assert!(
+                simulation.asset_reserve.abs_diff(reference.asset_reserve)
+                    <= asset_out / 10_000 + fragment
+            );
+
+            // the batch is cleared on the curve, rounded in favour of the pool
+            let (currency_reserve, asset_reserve) =
+                AntiMevAmm::clear_batch(&pair, currency, asset).expect("valid batch");
+            let product = pair.currency_reserve * pair.token_reserve;
+            assert!(currency_reserve * asset_reserve >= product);
+            assert!(currency_reserve * asset_reserve - product <= currency_reserve + asset_reserve);
+        }
+    });
+}
+
+#[test]
+fn should_charge_provider_fee_and_update_reserves_on_settlement() {
+    new_test_ext().execute_with(|| {
+        let pair = AntiMevAmm::pairs(ASSET_A).expect("pair exists");
+        let bob_asset_before = <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_BOB);
+        let amount_in = 1_000_000_000;
+        let epoch_end = 1 + <<TestRuntime as Config>::EpochLength as Get<u64>>::get();
+        assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            amount_in,
+            1,
+            epoch_end
+        ));
+        System::set_block_number(epoch_end);
+        assert_ok!(AntiMevAmm::settle_and_distribute(
+            RuntimeOrigin::signed(ACCOUNT_CHARLIE),
+            ASSET_A,
+        ));
+        claim_all();
+
+        // a lone intent pays the same fee as an instant swap
+        let instant_out = AntiMevAmm::cp_get_output_amount(
+            &amount_in,
+            &pair.currency_reserve,
+            &pair.token_reserve,
+        ).expect("valid swap");
+        let bob_out = <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_BOB) - bob_asset_before;
+        assert!(bob_out.abs_diff(instant_out) <= 10);
+
+        // the reserves follow the settlement and keep the fee
+        let settled_pair = AntiMevAmm::pairs(ASSET_A).expect("pair exists");
+        assert_eq!(settled_pair.currency_reserve, pair.currency_reserve + amount_in);
+        assert_eq!(settled_pair.token_reserve, pair.token_reserve - bob_out);
+        assert!(
+            settled_pair.currency_reserve * settled_pair.token_reserve
+                > pair.currency_reserve * pair.token_reserve
+        );
+    });
+}
+
+#[test]
+fn should_net_opposing_flows_at_spot_price() {
+    new_test_ext().execute_with(|| {
+        let pair = AntiMevAmm::pairs(ASSET_A).expect("pair exists");
+        let bob_asset_before = <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_BOB);
+        let alice_currency_before = Balances::free_balance(ACCOUNT_ALICE);
+        let epoch_end = 1 + <<TestRuntime as Config>::EpochLength as Get<u64>>::get();
+        assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            3_000_000_000,
+            1,
+            epoch_end
+        ));
+        assert_ok!(AntiMevAmm::add_swap_asset_for_currency(
+            RuntimeOrigin::signed(ACCOUNT_ALICE),
+            ASSET_A,
+            1_000_000_000,
+            1,
+            epoch_end
+        ));
+        System::set_block_number(epoch_end);
+        assert_ok!(AntiMevAmm::settle_and_distribute(
+            RuntimeOrigin::signed(ACCOUNT_CHARLIE),
+            ASSET_A,
+        ));
+        claim_all();
+
+        // Alice is matched with Bob at the spot price, without fee
+        assert_eq!(Balances::free_balance(ACCOUNT_ALICE), alice_currency_before + 1_000_000_000);
+
+        // only Bob's imbalance goes through the curve
+        let routed_out = AntiMevAmm::cp_get_output_amount(
+            &2_000_000_000,
+            &pair.currency_reserve,
+            &pair.token_reserve,
+        ).expect("valid swap");
+        let bob_out = <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_BOB) - bob_asset_before;
+        assert_eq!(bob_out, 1_000_000_000 + routed_out);
+        let settled_pair = AntiMevAmm::pairs(ASSET_A).expect("pair exists");
+        assert_eq!(settled_pair.currency_reserve, pair.currency_reserve + 2_000_000_000);
+        assert_eq!(settled_pair.token_reserve, pair.token_reserve - routed_out);
+        System::assert_has_event(
+            Event::<TestRuntime>::DistributeSettlement(
+                ASSET_A,
+                1_000_000_000,
+                bob_out,
+                1_000_000_000,
+                1_000_000_000,
+                2_000_000_000,
+                0,
+            ).into()
+        );
+    });
+}
+
+#[test]
+fn should_reward_executor_from_pot_for_free() {
+    new_test_ext().execute_with(|| {
+        let reward = <<TestRuntime as Config>::ExecutorReward as Get<u128>>::get();
+        assert_ok!(Balances::transfer_allow_death(
+            RuntimeOrigin::signed(ACCOUNT_ALICE),
+            <TestRuntime as ConfigHelper>::reward_pot(),
+            1_000 * reward
+        ));
+        let charlie_before = Balances::free_balance(ACCOUNT_CHARLIE);
+
+        // the dust batch nets at the spot price and pays no fee to reward
+        queue_full_batch(ASSET_A);
+        assert_ok!(AntiMevAmm::settle_and_distribute(RuntimeOrigin::signed(ACCOUNT_CHARLIE), ASSET_A));
+        assert_eq!(Balances::free_balance(ACCOUNT_CHARLIE), charlie_before);
+
+        // the fee on the routed volume covers the reward
+        for buyer in [ACCOUNT_BOB, ACCOUNT_DAVE] {
+            assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+                RuntimeOrigin::signed(buyer),
+                ASSET_A,
+                1_000_000,
+                1,
+                System::block_number().saturating_add(1)
+            ));
+        }
+        for seller in [ACCOUNT_ALICE, ACCOUNT_ATTACKER] {
+            assert_ok!(AntiMevAmm::add_swap_asset_for_currency(
+                RuntimeOrigin::signed(seller),
+                ASSET_A,
+                100,
+                1,
+                System::block_number().saturating_add(1)
+            ));
+        }
+        let post_info = AntiMevAmm::settle_and_distribute(RuntimeOrigin::signed(ACCOUNT_CHARLIE), ASSET_A)
+            .expect("settlement succeeds");
+        assert_eq!(post_info.pays_fee, Pays::No);
+        System::assert_has_event(Event::<TestRuntime>::ExecutorRewarded(ACCOUNT_CHARLIE, reward).into());
+        assert_eq!(Balances::free_balance(ACCOUNT_CHARLIE), charlie_before + reward);
+    });
+}
+
+#[test]
+fn should_settle_without_reward_when_pot_is_empty() {
+    new_test_ext().execute_with(|| {
+        queue_full_batch(ASSET_A);
+        let charlie_before = Balances::free_balance(ACCOUNT_CHARLIE);
+
+        assert_ok!(AntiMevAmm::settle_and_distribute(RuntimeOrigin::signed(ACCOUNT_CHARLIE), ASSET_A));
+        assert!(is_queue_empty(ASSET_A));
+        assert_eq!(Balances::free_balance(ACCOUNT_CHARLIE), charlie_before);
+    });
+}
+
+#[test]
+fn should_fund_buy_leg_with_sell_leg_proceeds() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(AntiMevAmm::create_pair(
+            RuntimeOrigin::signed(ACCOUNT_ALICE),
+            ASSET_B,
+            LIQ_TOKEN_B,
+            INIT_LIQUIDITY,
+            INIT_LIQUIDITY
+        ));
+        let bob_currency_before = Balances::free_balance(ACCOUNT_BOB);
+        let bob_asset_b_before = <TestRuntime as Config>::Assets::balance(ASSET_B, ACCOUNT_BOB);
+        assert_ok!(AntiMevAmm::add_swap_asset_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            ASSET_B,
+            1_000_000_000,
+            1,
+            System::block_number().saturating_add(100)
+        ));
+        System::set_block_number(1 + <<TestRuntime as Config>::EpochLength as Get<u64>>::get());
+
+        // settling the bought pair first has nothing to do yet
+        assert_noop!(
+            AntiMevAmm::settle_and_distribute(RuntimeOrigin::signed(ACCOUNT_CHARLIE), ASSET_B),
+            Error::<TestRuntime>::QueueTooSmall
+        );
+
+        assert_ok!(AntiMevAmm::settle_and_distribute(RuntimeOrigin::signed(ACCOUNT_CHARLIE), ASSET_A));
+        claim_all();
+        let currency_out = System::events()
+            .into_iter()
+            .find_map(|record| match record.event {
+                RuntimeEvent::AntiMevAmm(Event::SwappedAssetForCurrency(_, ACCOUNT_BOB, _, currency_out, _)) =>
+                    Some(currency_out),
+                _ => None,
+            })
+            .expect("sell leg settled");
+        let buy_leg = AntiMevAmm::get_pair_currency_queue(&ASSET_B).expect("pair exists");
+        assert_eq!(buy_leg.len(), 1);
+        assert_eq!(
+            AntiMevAmm::currency_intents(buy_leg[0]),
+            Some(Intent {
+                owner: ACCOUNT_BOB,
+                swap: AntiMevSwap::BasedInput { input_amount: currency_out, min_output: 1 },
+                deadline: 101,
+                queued_at: System::block_number(),
+            })
+        );
+        assert!(AntiMevAmm::next_legs(0).is_none());
+
+        System::set_block_number(System::block_number() + <<TestRuntime as Config>::EpochLength as Get<u64>>::get());
+        assert_ok!(AntiMevAmm::settle_and_distribute(RuntimeOrigin::signed(ACCOUNT_CHARLIE), ASSET_B));
+        claim_all();
+        assert!(<TestRuntime as Config>::Assets::balance(ASSET_B, ACCOUNT_BOB) > bob_asset_b_before);
+        // the currency never left the pallet
+        assert_eq!(Balances::free_balance(ACCOUNT_BOB), bob_currency_before);
+    });
+}
+
+#[test]
+fn should_settle_both_legs_in_one_pass() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(AntiMevAmm::create_pair(
+            RuntimeOrigin::signed(ACCOUNT_ALICE),
+            ASSET_B,
+            LIQ_TOKEN_B,
+            INIT_LIQUIDITY,
+            INIT_LIQUIDITY
+        ));
+        let bob_currency_before = Balances::free_balance(ACCOUNT_BOB);
+        let bob_asset_b_before = <TestRuntime as Config>::Assets::balance(ASSET_B, ACCOUNT_BOB);
+        let epoch_end = 1 + <<TestRuntime as Config>::EpochLength as Get<u64>>::get();
+        assert_ok!(AntiMevAmm::add_swap_asset_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            ASSET_B,
+            1_000_000_000,
+            1,
+            epoch_end
+        ));
+        assert_noop!(
+            AntiMevAmm::settle_all(RuntimeOrigin::signed(ACCOUNT_CHARLIE), 2),
+            Error::<TestRuntime>::QueueTooSmall
+        );
+        System::set_block_number(epoch_end);
+
+        // the bought pair settles in the same pass
+        assert_noop!(
+            AntiMevAmm::settle_all(RuntimeOrigin::signed(ACCOUNT_CHARLIE), 1),
+            Error::<TestRuntime>::TooManyPairs
+        );
+        assert_ok!(AntiMevAmm::settle_all(RuntimeOrigin::signed(ACCOUNT_CHARLIE), 2));
+        claim_all();
+        let currency_out = System::events()
+            .into_iter()
+            .find_map(|record| match record.event {
+                RuntimeEvent::AntiMevAmm(Event::SwappedAssetForCurrency(ASSET_A, ACCOUNT_BOB, _, currency_out, _)) =>
+                    Some(currency_out),
+                _ => None,
+            })
+            .expect("sell leg settled");
+        let asset_out = System::events()
+            .into_iter()
+            .find_map(|record| match record.event {
+                RuntimeEvent::AntiMevAmm(Event::SwappedCurrencyForAsset(ASSET_B, ACCOUNT_BOB, _, currency_in, asset_out)) => {
+                    assert_eq!(currency_in, currency_out);
+                    Some(asset_out)
+                }
+                _ => None,
+            })
+            .expect("buy leg settled");
+        assert_eq!(<TestRuntime as Config>::Assets::balance(ASSET_B, ACCOUNT_BOB), bob_asset_b_before + asset_out);
+        assert_eq!(Balances::free_balance(ACCOUNT_BOB), bob_currency_before);
+        assert!(is_queue_empty(ASSET_A));
+        assert!(is_queue_empty(ASSET_B));
+        assert!(AntiMevAmm::next_legs(0).is_none());
+        assert_eq!(
+            AntiMevAmm::pairs(ASSET_B).expect("pair exists").currency_reserve,
+            INIT_LIQUIDITY + currency_out
+        );
+    });
+}
+
+#[test]
+fn should_settle_all_up_to_pair_count() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(AntiMevAmm::create_pair(
+            RuntimeOrigin::signed(ACCOUNT_ALICE),
+            ASSET_B,
+            LIQ_TOKEN_B,
+            INIT_LIQUIDITY,
+            INIT_LIQUIDITY
+        ));
+        queue_full_batch(ASSET_A);
+        queue_full_batch(ASSET_B);
+
+        // the pass stops at the first mature pair, the other one waits
+        let post_info = AntiMevAmm::settle_all(RuntimeOrigin::signed(ACCOUNT_CHARLIE), 1)
+            .expect("settlement succeeds");
+        assert_eq!(post_info.actual_weight, Some(AntiMevAmm::settle_all_weight(1, 1)));
+        assert_ne!(is_queue_empty(ASSET_A), is_queue_empty(ASSET_B));
+
+        assert_ok!(AntiMevAmm::settle_all(RuntimeOrigin::signed(ACCOUNT_CHARLIE), 1));
+        assert!(is_queue_empty(ASSET_A));
+        assert!(is_queue_empty(ASSET_B));
+    });
+}
+
+#[test]
+fn should_net_ring_trades_across_pairs() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(AntiMevAmm::create_pair(
+            RuntimeOrigin::signed(ACCOUNT_ALICE),
+            ASSET_B,
+            LIQ_TOKEN_B,
+            INIT_LIQUIDITY,
+            INIT_LIQUIDITY
+        ));
+        let amount_in = 1_000_000_000;
+        let bob_asset_b_before = <TestRuntime as Config>::Assets::balance(ASSET_B, ACCOUNT_BOB);
+        let attacker_asset_a_before = <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_ATTACKER);
+        let epoch_end = 1 + <<TestRuntime as Config>::EpochLength as Get<u64>>::get();
+        assert_ok!(AntiMevAmm::add_swap_asset_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            ASSET_B,
+            amount_in,
+            1,
+            epoch_end
+        ));
+        assert_ok!(AntiMevAmm::add_swap_asset_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_ATTACKER),
+            ASSET_B,
+            ASSET_A,
+            amount_in,
+            1,
+            epoch_end
+        ));
+        System::set_block_number(epoch_end);
+        assert_ok!(AntiMevAmm::settle_all(RuntimeOrigin::signed(ACCOUNT_CHARLIE), 2));
+        claim_all();
+
+        // the opposite legs net at the spot price in both pairs, only rounding
+        // dust reaches the reserves
+        let bob_out = <TestRuntime as Config>::Assets::balance(ASSET_B, ACCOUNT_BOB) - bob_asset_b_before;
+        let attacker_out =
+            <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_ATTACKER) - attacker_asset_a_before;
+        assert!(bob_out.abs_diff(amount_in) <= 2);
+        assert!(attacker_out.abs_diff(amount_in) <= 2);
+        for asset_id in [ASSET_A, ASSET_B] {
+            let pair = AntiMevAmm::pairs(asset_id).expect("pair exists");
+            assert!(pair.currency_reserve.abs_diff(INIT_LIQUIDITY) <= 2);
+            assert!(pair.token_reserve.abs_diff(INIT_LIQUIDITY) <= 2);
+            assert!(is_queue_empty(asset_id));
+        }
+    });
+}
+
+#[test]
+fn should_charge_exact_output_intent_at_clearing_price() {
+    new_test_ext().execute_with(|| {
+        let bob_currency_before = Balances::free_balance(ACCOUNT_BOB);
+        let bob_asset_before = <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_BOB);
+        let output_amount = 1_000_000;
+        let max_input = 2_000_000;
+
+        assert_ok!(AntiMevAmm::add_swap_currency_for_exact_asset(
+            RuntimeOrigin::signed(ACCOUNT_BOB),
+            ASSET_A,
+            output_amount,
+            max_input,
+            System::block_number().saturating_add(1)
+        ));
+        assert_eq!(Balances::free_balance(ACCOUNT_BOB), bob_currency_before - max_input);
+        assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+            RuntimeOrigin::signed(ACCOUNT_ATTACKER),
+            ASSET_A,
+            1_000_000,
+            1,
+            System::block_number().saturating_add(1)
+        ));
+        for seller in [ACCOUNT_ALICE, ACCOUNT_ATTACKER] {
+            assert_ok!(AntiMevAmm::add_swap_asset_for_currency(
+                RuntimeOrigin::signed(seller),
+                ASSET_A,
+                1_000_000,
+                1,
+                System::block_number().saturating_add(1)
+            ));
+        }
+
+        assert_ok!(AntiMevAmm::settle_and_distribute(RuntimeOrigin::signed(ACCOUNT_CHARLIE), ASSET_A));
+        claim_all();
+        let charged = System::events()
+            .into_iter()
+            .find_map(|record| match record.event {
+                RuntimeEvent::AntiMevAmm(Event::SwappedCurrencyForAsset(_, ACCOUNT_BOB, _, amount_in, amount_out)) => {
+                    assert_eq!(amount_out, output_amount);
+                    Some(amount_in)
+                }
+                _ => None,
+            })
+            .expect("intent settled");
+        assert!(charged < max_input);
+        // the unused input is back
+        assert_eq!(Balances::free_balance(ACCOUNT_BOB), bob_currency_before - charged);
+        assert_eq!(
+            <TestRuntime as Config>::Assets::balance(ASSET_A, ACCOUNT_BOB),
+            bob_asset_before + output_amount
+        );
+    });
+}
+
+
+#[test]
+fn should_not_move_batch_price_with_unused_exact_output_input() {
+    // the fills of the batch don't depend on the maximum input of an exact output intent
+    let settle_with_max_input = |max_input| {
+        new_test_ext().execute_with(|| {
+            assert_ok!(AntiMevAmm::add_swap_currency_for_exact_asset(
+                RuntimeOrigin::signed(ACCOUNT_BOB),
+                ASSET_A,
+                1_000_000_000,
+                max_input,
+                System::block_number().saturating_add(1)
+            ));
+            assert_ok!(AntiMevAmm::add_swap_currency_for_asset(
+                RuntimeOrigin::signed(ACCOUNT_ATTACKER),
+                ASSET_A,
+                1_000_000_000,
+                1,
+                System::block_number().saturating_add(1)
+            ));
+            for seller in [ACCOUNT_ALICE, ACCOUNT_ATTACKER] {
+                assert_ok!(AntiMevAmm::add_swap_asset_for_currency(
+                    RuntimeOrigin::signed(seller),
+                    ASSET_A,
+                    1_000_000,
+                    1,
+                    System::block_number().saturating_add(1)
+                ));
+            }
+            assert_ok!(AntiMevAmm::settle_and_distribute(RuntimeOrigin::signed(ACCOUNT_CHARLIE), ASSET_A));
+            let fills: Vec<_> = System::events()
+                .into_iter()
+                .filter_map(|record| match record.event {
+                    RuntimeEvent::AntiMevAmm(Event::SwappedCurrencyForAsset(_, buyer, _, amount_in, amount_out)) => {
+                        Some((buyer, amount_in, amount_out))
+                    }
+                    _ => None,
+                })
+                .collect();
+            let pair = AntiMevAmm::pairs(ASSET_A).expect("pair exists");
+            (fills, pair.currency_reserve, pair.token_reserve)
+        })
+    };
+
+    let (fills, currency_reserve, token_reserve) = settle_with_max_input(2_000_000_000);
+    assert_eq!(fills.len(), 2);
+    assert_eq!(settle_with_max_input(100_000_000_000), (fills, currency_reserve, token_reserve));
+}
+
+#[test]
+fn should_fail_settlement_arithmetic_instead_of_wrapping() {
+    new_test_ext().execute_with(|| {
+        assert_eq!(AntiMevAmm::pro_rata(u128::MAX, 2, 4), Err(ArithmeticError::Overflow));
+        assert_eq!(AntiMevAmm::pro_rata(100, 1, 0), Err(ArithmeticError::DivisionByZero));
+        assert_eq!(AntiMevAmm::pro_rata(100, 1, 3), Ok(33));
+        assert_eq!(AntiMevAmm::clearing_input(u128::MAX, 2, 3), Err(ArithmeticError::Overflow));
+        assert_eq!(AntiMevAmm::clearing_input(100, 1, 3), Ok(34));
+    });
+}
@@ -1,4 +1,6 @@
 use super::*;
+use frame_system::pallet_prelude::BlockNumberFor;
+use sp_std::collections::btree_map::BTreeMap;
 
 /// This struct represents a pair in the AMM.
 /// It contains the (asset id, the currency reserve, the token reserve, and the liquidity token id).
@@ -33,15 +35,274 @@ pub enum CpSwap<InputBalance, OutputBalance> {
 pub enum AntiMevSwap<InputBalance, OutputBalance> {
     BasedInput {
         input_amount: InputBalance,
+        min_output: OutputBalance,
     },
     BasedOutput {
+        max_input: InputBalance,
         output_amount: OutputBalance,
     },
 }
 
+impl<InputBalance: Copy, OutputBalance: Copy> AntiMevSwap<InputBalance, OutputBalance> {
+    /// The input locked until settlement.
+    pub fn locked_input(&self) -> InputBalance {
+        match self {
+            AntiMevSwap::BasedInput { input_amount, .. } => *input_amount,
+            AntiMevSwap::BasedOutput { max_input, .. } => *max_input,
+        }
+    }
+
+    /// The output the batch has to pay at least.
+    pub fn min_output(&self) -> OutputBalance {
+        match self {
+            AntiMevSwap::BasedInput { min_output, .. } => *min_output,
+            AntiMevSwap::BasedOutput { output_amount, .. } => *output_amount,
+        }
+    }
+}
+
+/// This enum represents the direction of a queued anti-mev swap.
+#[derive(Clone, Copy, Encode, Decode, Eq, PartialEq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub enum SwapDirection {
+    CurrencyToAsset,
+    AssetToCurrency,
+}
+
+/// This enum represents a side of a swap routed by `swap`.
+/// It is either the currency or an asset traded against it.
+#[derive(Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub enum SwapAsset<AssetId> {
+    Currency,
+    Asset(AssetId),
+}
+
+/// This enum represents the path a swap routed by `swap` took.
+#[derive(Clone, Copy, Encode, Decode, Eq, PartialEq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub enum SwapPath {
+    /// Executed on the constant-product curve
+    Instant,
+    /// Queued into the anti-mev batch
+    Batched,
+}
+
+/// This struct represents a queued swap intent.
+/// It contains the (account receiving the output, the swap and its limits,
+/// last block the intent can be settled in, block the intent was queued in).
+#[derive(Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub struct Intent<AccountId, InputBalance, OutputBalance, BlockNumber> {
+    pub owner: AccountId,
+    pub swap: AntiMevSwap<InputBalance, OutputBalance>,
+    pub deadline: BlockNumber,
+    pub queued_at: BlockNumber,
+}
+
+/// This struct represents the bounds of an intent queue.
+/// It contains the (index of the first intent, index after the last intent).
+#[derive(
+    Clone, Copy, Encode, Decode, Eq, PartialEq, RuntimeDebug, Default, MaxEncodedLen, TypeInfo,
+)]
+pub struct QueueIndex {
+    pub head: u32,
+    pub tail: u32,
+}
+
+impl QueueIndex {
+    pub fn len(&self) -> u32 {
+        self.tail.wrapping_sub(self.head)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head == self.tail
+    }
+}
+
+/// This struct represents a swap intent signed off-chain and submitted by a relayer.
+/// It contains the (account signing and paying the intent, asset id, direction of the swap,
+/// input amount, minimum output, last block the intent can be settled in, nonce of the signer).
+/// The amounts of an asset to currency intent are converted with `CurrencyToAssetBalance`.
+/// The signer signs `Pallet::signed_intent_payload` of the intent.
+#[derive(Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub struct SignedIntent<AccountId, AssetId, Balance, BlockNumber> {
+    pub signer: AccountId,
+    pub asset_id: AssetId,
+    pub direction: SwapDirection,
+    pub amount_in: Balance,
+    pub min_output: Balance,
+    pub deadline: BlockNumber,
+    pub nonce: u64,
+}
+
+/// This struct represents a hidden swap intent waiting to be revealed.
+/// It contains the (reserved deposit, the block of the commitment).
+#[derive(Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub struct Commitment<Balance, BlockNumber> {
+    pub deposit: Balance,
+    pub committed_at: BlockNumber,
+}
+
+/// This struct represents the settlement payout of an intent waiting to be claimed.
+/// It contains the (account receiving the payout, currency amount, asset amount).
+#[derive(Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub struct Claim<AccountId, Balance, AssetBalance> {
+    pub owner: AccountId,
+    pub currency: Balance,
+    pub asset: AssetBalance,
+}
+
+/// This struct represents a simulated batch settlement.
+/// It contains the (currency reserve, asset reserve) the batch settles on,
+/// including the provider fee the batch pays, the (currency, asset) matched
+/// against each other at the spot price and the (currency, asset) routed
+/// through the curve.
+#[derive(Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub struct SettlementSimulation<Balance> {
+    pub currency_reserve: Balance,
+    pub asset_reserve: Balance,
+    pub netted_currency: Balance,
+    pub netted_asset: Balance,
+    pub routed_currency: Balance,
+    pub routed_asset: Balance,
+}
+
+/// This struct represents the projected payout of an intent in a settlement.
+/// It contains the (intent id, account receiving the payout, direction of the swap,
+/// whether the intent settles or is refunded, currency amount, asset amount).
+/// The currency of a sell leg funds its buy leg.
+#[derive(Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, TypeInfo)]
+pub struct IntentPayout<AccountId, Balance, AssetBalance> {
+    pub intent_id: IntentId,
+    pub owner: AccountId,
+    pub direction: SwapDirection,
+    pub settled: bool,
+    pub currency: Balance,
+    pub asset: AssetBalance,
+}
+
+/// This struct represents the outcome of the next settlement of a pair.
+/// It contains the (currency paid per asset bought, currency received per asset sold,
+/// currency reserve, asset reserve the pair settles on, payout of each intent).
+#[derive(Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, TypeInfo)]
+pub struct SettlementPreview<AccountId, Balance, AssetBalance> {
+    pub buy_price: FixedU128,
+    pub sell_price: FixedU128,
+    pub currency_reserve: Balance,
+    pub token_reserve: AssetBalance,
+    pub payouts: Vec<IntentPayout<AccountId, Balance, AssetBalance>>,
+}
+
+/// This struct represents the fill of an intent in a settlement proposal.
+/// It contains the (intent id, input taken from the intent, output paid to the intent).
+#[derive(Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, MaxEncodedLen, TypeInfo)]
+pub struct Fill<InputBalance, OutputBalance> {
+    pub intent_id: IntentId,
+    pub amount_in: InputBalance,
+    pub amount_out: OutputBalance,
+}
+
+/// This struct represents a settlement of a batch proposed by a solver.
+/// It contains the (fills of the currency to asset intents, fills of the asset to
+/// currency intents, currency reserve, asset reserve the pair settles on).
+/// The intents of the batch without a fill are refunded.
+#[derive(
+    CloneNoBound, Encode, Decode, EqNoBound, PartialEqNoBound, RuntimeDebugNoBound, MaxEncodedLen,
+    TypeInfo,
+)]
+#[scale_info(skip_type_params(T))]
+#[codec(mel_bound())]
+pub struct SettlementProposal<T: Config> {
+    pub buy_fills: BoundedVec<Fill<BalanceOf<T>, AssetBalanceOf<T>>, T::MaxQueueLength>,
+    pub sell_fills: BoundedVec<Fill<AssetBalanceOf<T>, BalanceOf<T>>, T::MaxQueueLength>,
+    pub currency_reserve: BalanceOf<T>,
+    pub token_reserve: AssetBalanceOf<T>,
+}
+
+/// This struct represents the best settlement proposal of a batch.
+/// It contains the (solver proposing the settlement, the proposal,
+/// surplus of the intents over their limits valued in currency).
+#[derive(
+    CloneNoBound, Encode, Decode, EqNoBound, PartialEqNoBound, RuntimeDebugNoBound, MaxEncodedLen,
+    TypeInfo,
+)]
+#[scale_info(skip_type_params(T))]
+#[codec(mel_bound())]
+pub struct Bid<T: Config> {
+    pub solver: AccountIdOf<T>,
+    pub proposal: SettlementProposal<T>,
+    pub surplus: BalanceOf<T>,
+}
+
+/// This struct represents the queued intents of a pair taken into a settlement.
+/// It contains the (asset id, pair, live intents oldest first, intents past their deadline).
+pub(crate) struct PairBatch<T: Config> {
+    pub asset_id: AssetIdOf<T>,
+    pub pair: PairOf<T>,
+    pub buys: Vec<(IntentId, CurrencyToAssetIntentOf<T>)>,
+    pub sells: Vec<(IntentId, AssetToCurrencyIntentOf<T>)>,
+    pub expired_buys: Vec<(IntentId, CurrencyToAssetIntentOf<T>)>,
+    pub expired_sells: Vec<(IntentId, AssetToCurrencyIntentOf<T>)>,
+}
+
+/// This struct represents a batch cleared against its pair.
+/// It contains the (intents taken into the batch, intents whose limits are met
+/// at the batch price, the inputs the exact output intents among them trade,
+/// their total inputs and the settlement they clear on).
+pub(crate) struct ClearedBatch<T: Config> {
+    pub batch_buys: Vec<(IntentId, CurrencyToAssetIntentOf<T>)>,
+    pub batch_sells: Vec<(IntentId, AssetToCurrencyIntentOf<T>)>,
+    pub buys: Vec<(IntentId, CurrencyToAssetIntentOf<T>)>,
+    pub sells: Vec<(IntentId, AssetToCurrencyIntentOf<T>)>,
+    pub buy_inputs: BTreeMap<IntentId, BalanceOf<T>>,
+    pub sell_inputs: BTreeMap<IntentId, AssetBalanceOf<T>>,
+    pub total_cumulative_currency: BalanceOf<T>,
+    pub total_cumulative_asset: BalanceOf<T>,
+    pub simulation: SettlementSimulation<BalanceOf<T>>,
+}
+
+impl<T: Config> ClearedBatch<T> {
+    /// The input a currency to asset intent trades in the batch.
+    pub fn buy_input(&self, intent_id: &IntentId, intent: &CurrencyToAssetIntentOf<T>) -> BalanceOf<T> {
+        self.buy_inputs.get(intent_id).copied().unwrap_or_else(|| intent.swap.locked_input())
+    }
+
+    /// The input an asset to currency intent trades in the batch.
+    pub fn sell_input(
+        &self,
+        intent_id: &IntentId,
+        intent: &AssetToCurrencyIntentOf<T>,
+    ) -> AssetBalanceOf<T> {
+        self.sell_inputs.get(intent_id).copied().unwrap_or_else(|| intent.swap.locked_input())
+    }
+
+    /// The (currency paid to the sellers, asset paid to the buyers) of the batch.
+    pub fn outputs(
+        &self,
+        pair: &PairOf<T>,
+    ) -> Result<(BalanceOf<T>, BalanceOf<T>), ArithmeticError> {
+        Pallet::<T>::batch_outputs(
+            pair,
+            self.total_cumulative_currency,
+            self.total_cumulative_asset,
+            &self.simulation,
+        )
+    }
+}
 
 // (sold_token_amount, currency_amount, bought_token_amount)
 pub type AssetToAssetPrice<T> = (AssetBalanceOf<T>, BalanceOf<T>, AssetBalanceOf<T>);
 
 // Type alias for convenience
 pub type PairOf<T> = Pair<AssetIdOf<T>, BalanceOf<T>, AssetBalanceOf<T>>;
+pub type IntentId = u64;
+pub type BatchIntentIdsOf<T> = BoundedVec<IntentId, <T as Config>::MaxQueueLength>;
+pub type CurrencyToAssetIntentOf<T> =
+    Intent<AccountIdOf<T>, BalanceOf<T>, AssetBalanceOf<T>, BlockNumberFor<T>>;
+pub type AssetToCurrencyIntentOf<T> =
+    Intent<AccountIdOf<T>, AssetBalanceOf<T>, BalanceOf<T>, BlockNumberFor<T>>;
+pub type CommitmentOf<T> = Commitment<BalanceOf<T>, BlockNumberFor<T>>;
+pub type SignedIntentOf<T> =
+    SignedIntent<AccountIdOf<T>, AssetIdOf<T>, BalanceOf<T>, BlockNumberFor<T>>;
+pub type ClaimOf<T> = Claim<AccountIdOf<T>, BalanceOf<T>, AssetBalanceOf<T>>;
+pub type IntentPayoutOf<T> = IntentPayout<AccountIdOf<T>, BalanceOf<T>, AssetBalanceOf<T>>;
+pub type SettlementProposalOf<T> = SettlementProposal<T>;
+pub type BidOf<T> = Bid<T>;
+pub type SettlementPreviewOf<T> = SettlementPreview<AccountIdOf<T>, BalanceOf<T>, AssetBalanceOf<T>>;
@@ -1,29 +1,16 @@
 
-//! Autogenerated weights for pallet_template
+//! Weights for pallet_anti_mev_amm
 //!
-//! THIS FILE WAS AUTO-GENERATED USING THE SUBSTRATE BENCHMARK CLI VERSION 4.0.0-dev
-//! DATE: 2023-04-06, STEPS: `50`, REPEAT: `20`, LOW RANGE: `[]`, HIGH RANGE: `[]`
-//! WORST CASE MAP SIZE: `1000000`
-//! HOSTNAME: `Alexs-MacBook-Pro-2.local`, CPU: `<UNKNOWN>`
-//! EXECUTION: Some(Wasm), WASM-EXECUTION: Compiled, CHAIN: Some("dev"), DB CACHE: 1024
-
-// Executed Command:
-// ../../target/release/node-template
-// benchmark
-// pallet
-// --chain
-// dev
-// --pallet
-// pallet_template
-// --extrinsic
-// *
-// --steps=50
-// --repeat=20
-// --wasm-execution=compiled
-// --output
-// pallets/template/src/weights.rs
-// --template
-// ../../.maintain/frame-weight-template.hbs
+//! NOT GENERATED BY THE BENCHMARK CLI: the workspace has no node to run the
+//! benchmarks on, so these weights are estimated by hand. The reads and writes
+//! are counted along the worst path of each call, and the execution time is a
+//! conservative guess per call plus per intent, fill or queue slot handled.
+//!
+//! Replace them with the output of the benchmarks in `benchmarking.rs`, run on
+//! a node built with `--features runtime-benchmarks`:
+//!
+//! <node> benchmark pallet --chain dev --pallet pallet_anti_mev_amm --extrinsic '*'
+//!     --steps 50 --repeat 20 --output pallets/anti-mev-amm/src/weights.rs
 
 #![cfg_attr(rustfmt, rustfmt_skip)]
 #![allow(unused_parens)]
@@ -33,18 +20,65 @@
 use frame_support::{traits::Get, weights::{Weight, constants::RocksDbWeight}};
 use core::marker::PhantomData;
 
-/// Weight functions needed for pallet_template.
+/// Weight functions needed for pallet_anti_mev_amm.
 pub trait WeightInfo {
 	fn default() -> Weight;
+	/// Settling a pair reading `s` empty queue slots and settling `i` intents.
+	fn settle_and_distribute(s: u32, i: u32) -> Weight;
+	/// Checking a proposal of `f` fills against a batch of `i` intents.
+	fn submit_settlement(i: u32, f: u32) -> Weight;
+	/// Cancelling the intents of an account found in `s` queue slots.
+	fn cancel_swap_intent(s: u32) -> Weight;
+	/// Revealing a committed swap intent into its queue.
+	fn reveal_swap_intent() -> Weight;
+	/// Verifying and queueing `n` signed intents.
+	fn submit_signed_intents(n: u32) -> Weight;
 }
 
-/// Weights for pallet_stable_amm using the Substrate node and recommended hardware.
+/// Weights for pallet_anti_mev_amm, estimated by hand until they are benchmarked.
 pub struct SubstrateWeight<T>(PhantomData<T>);
 impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
 	fn default() -> Weight {
 					Weight::from_parts(1_000, 0)
 						.saturating_add(T::DbWeight::get().writes(1_u64))
 	}
+	fn settle_and_distribute(s: u32, i: u32) -> Weight {
+					Weight::from_parts(50_000_000, 0)
+						.saturating_add(Weight::from_parts(5_000_000, 0).saturating_mul(i.into()))
+						.saturating_add(T::DbWeight::get().reads(12_u64))
+						.saturating_add(T::DbWeight::get().reads((1_u64).saturating_mul(s.into())))
+						.saturating_add(T::DbWeight::get().reads((1_u64).saturating_mul(i.into())))
+						.saturating_add(T::DbWeight::get().writes(12_u64))
+						.saturating_add(T::DbWeight::get().writes((1_u64).saturating_mul(s.into())))
+						.saturating_add(T::DbWeight::get().writes((2_u64).saturating_mul(i.into())))
+	}
+	fn submit_settlement(i: u32, f: u32) -> Weight {
+					Weight::from_parts(30_000_000, 0)
+						.saturating_add(Weight::from_parts(4_000_000, 0).saturating_mul(i.into()))
+						.saturating_add(Weight::from_parts(1_000_000, 0).saturating_mul(f.into()))
+						.saturating_add(T::DbWeight::get().reads(8_u64))
+						.saturating_add(T::DbWeight::get().reads((2_u64).saturating_mul(i.into())))
+						.saturating_add(T::DbWeight::get().writes(2_u64))
+	}
+	fn cancel_swap_intent(s: u32) -> Weight {
+					Weight::from_parts(25_000_000, 0)
+						.saturating_add(Weight::from_parts(2_000_000, 0).saturating_mul(s.into()))
+						.saturating_add(T::DbWeight::get().reads(6_u64))
+						.saturating_add(T::DbWeight::get().reads((2_u64).saturating_mul(s.into())))
+						.saturating_add(T::DbWeight::get().writes(3_u64))
+						.saturating_add(T::DbWeight::get().writes((3_u64).saturating_mul(s.into())))
+	}
+	fn reveal_swap_intent() -> Weight {
+					Weight::from_parts(60_000_000, 0)
+						.saturating_add(T::DbWeight::get().reads(10_u64))
+						.saturating_add(T::DbWeight::get().writes(8_u64))
+	}
+	fn submit_signed_intents(n: u32) -> Weight {
+					Weight::from_parts(20_000_000, 0)
+						.saturating_add(Weight::from_parts(70_000_000, 0).saturating_mul(n.into()))
+						.saturating_add(T::DbWeight::get().reads((10_u64).saturating_mul(n.into())))
+						.saturating_add(T::DbWeight::get().writes((9_u64).saturating_mul(n.into())))
+	}
 }
 
 // For backwards compatibility and tests
@@ -53,4 +87,41 @@ impl WeightInfo for () {
 					Weight::from_parts(1_000, 0)
 						.saturating_add(RocksDbWeight::get().writes(1_u64))
 	}
+    fn settle_and_distribute(s: u32, i: u32) -> Weight {
+					Weight::from_parts(50_000_000, 0)
+						.saturating_add(Weight::from_parts(5_000_000, 0).saturating_mul(i.into()))
+						.saturating_add(RocksDbWeight::get().reads(12_u64))
+						.saturating_add(RocksDbWeight::get().reads((1_u64).saturating_mul(s.into())))
+						.saturating_add(RocksDbWeight::get().reads((1_u64).saturating_mul(i.into())))
+						.saturating_add(RocksDbWeight::get().writes(12_u64))
+						.saturating_add(RocksDbWeight::get().writes((1_u64).saturating_mul(s.into())))
+						.saturating_add(RocksDbWeight::get().writes((2_u64).saturating_mul(i.into())))
+	}
+	fn submit_settlement(i: u32, f: u32) -> Weight {
+					Weight::from_parts(30_000_000, 0)
+						.saturating_add(Weight::from_parts(4_000_000, 0).saturating_mul(i.into()))
+						.saturating_add(Weight::from_parts(1_000_000, 0).saturating_mul(f.into()))
+						.saturating_add(RocksDbWeight::get().reads(8_u64))
+						.saturating_add(RocksDbWeight::get().reads((2_u64).saturating_mul(i.into())))
+						.saturating_add(RocksDbWeight::get().writes(2_u64))
+	}
+	fn cancel_swap_intent(s: u32) -> Weight {
+					Weight::from_parts(25_000_000, 0)
+						.saturating_add(Weight::from_parts(2_000_000, 0).saturating_mul(s.into()))
+						.saturating_add(RocksDbWeight::get().reads(6_u64))
+						.saturating_add(RocksDbWeight::get().reads((2_u64).saturating_mul(s.into())))
+						.saturating_add(RocksDbWeight::get().writes(3_u64))
+						.saturating_add(RocksDbWeight::get().writes((3_u64).saturating_mul(s.into())))
+	}
+	fn reveal_swap_intent() -> Weight {
+					Weight::from_parts(60_000_000, 0)
+						.saturating_add(RocksDbWeight::get().reads(10_u64))
+						.saturating_add(RocksDbWeight::get().writes(8_u64))
+	}
+	fn submit_signed_intents(n: u32) -> Weight {
+					Weight::from_parts(20_000_000, 0)
+						.saturating_add(Weight::from_parts(70_000_000, 0).saturating_mul(n.into()))
+						.saturating_add(RocksDbWeight::get().reads((10_u64).saturating_mul(n.into())))
+						.saturating_add(RocksDbWeight::get().writes((9_u64).saturating_mul(n.into())))
+	}
 }
\ No newline at end of file
@@ -6,7 +6,7 @@ use frame::{
 		frame_support::{
 			genesis_builder_helper::{build_state, get_preset},
 			runtime,
-			traits::AsEnsureOriginWithArg,
+			traits::{AsEnsureOriginWithArg, Randomness},
 			weights::FixedFee,
             PalletId
 		},
@@ -23,7 +23,8 @@ use frame::{
 };
 use pallet_transaction_payment::{ConstFeeMultiplier, FeeDetails, Multiplier, RuntimeDispatchInfo};
 use sp_runtime::{
-    traits::{Convert, Identity},
+    traits::{BlakeTwo256, Convert, Hash as HashT, Identity, Verify},
+    Perbill,
 };
 
 #[runtime_version]
@@ -162,7 +163,7 @@ impl pallet_assets::Config for Runtime {
 	type MetadataDepositPerByte = MetadataDepositPerByte;
 	type ApprovalDeposit = ApprovalDeposit;
 	type StringLimit = StringLimit;
-	type Freezer = ();
+	type Freezer = LiquidityFreezer;
 	type Extra = ();
 	type WeightInfo = pallet_assets::weights::SubstrateWeight<Runtime>;
 	type RemoveItemsLimit = ConstU32<1000>;
@@ -171,6 +172,17 @@ impl pallet_assets::Config for Runtime {
 	type BenchmarkHelper = ();
 }
 
+/// Keeps the liquidity tokens locked by pallet-anti-mev-amm from being transferred.
+pub struct LiquidityFreezer;
+
+impl pallet_assets::FrozenBalance<u32, AccountId, u128> for LiquidityFreezer {
+	fn frozen_balance(asset: u32, who: &AccountId) -> Option<u128> {
+		Some(AntiMevAmm::locked_liquidity(&asset, who)).filter(|locked| *locked > 0)
+	}
+
+	fn died(_asset: u32, _who: &AccountId) {}
+}
+
 parameter_types! {
     pub const StableAmmPalletId: PalletId = PalletId(*b"par/stbl");
 }
@@ -184,7 +196,30 @@ impl pallet_stable_amm::Config for Runtime {
 
 parameter_types! {
     pub const AniMevAmmPalletId: PalletId = PalletId(*b"anti/mev");
+    pub const AntiMevRewardPotId: PalletId = PalletId(*b"mev/rwrd");
+    pub const CommitForfeit: Perbill = Perbill::from_percent(50);
+    // An instant swap may move the price by 2% in a block, larger trades go through the queue
+    pub const PriceBand: Option<Perbill> = Some(Perbill::from_percent(2));
+    // `swap` queues the orders moving the price by more than 0.5%
+    pub const DefaultImpactThreshold: Perbill = Perbill::from_perthousand(5);
 }
+/// Randomness mixing the parent block hash with the subject.
+/// The parent hash is known once the parent block is built, the pallet only
+/// shuffles the intents queued before it with this. The author of the parent
+/// block can still grind its hash, a runtime with BABE should use its VRF
+/// output instead.
+pub struct ParentHashRandomness;
+
+impl Randomness<Hash, BlockNumber> for ParentHashRandomness {
+    fn random(subject: &[u8]) -> (Hash, BlockNumber) {
+        let block_number = System::block_number();
+        (
+            BlakeTwo256::hash_of(&(System::parent_hash(), subject)),
+            block_number.saturating_sub(One::one()),
+        )
+    }
+}
+
 /// Configure the pallet-anti-mev-amm in pallets/anti-mev-amm.
 impl pallet_anti_mev_amm::Config for Runtime {
     type PalletId = AniMevAmmPalletId;
@@ -205,6 +240,48 @@ impl pallet_anti_mev_amm::Config for Runtime {
     type Fragment = ConstU32<10>;
     // Max queue amount is 2, there can be at most 4 transactions
     type MinQueueAmount = ConstU32<2>;
+    type MaxQueueLength = ConstU32<1_000>;
+    // A queue that is not full is settled after 10 blocks
+    type EpochLength = ConstU32<10>;
+    type CommitDeposit = ConstU128<1_000>;
+    // A commitment can be revealed from the next block, during 5 blocks
+    type RevealDelay = ConstU32<1>;
+    type RevealPeriod = ConstU32<5>;
+    type CommitForfeit = CommitForfeit;
+    type RewardPotId = AntiMevRewardPotId;
+    type ExecutorReward = ConstU128<100>;
+    type Randomness = ParentHashRandomness;
+    type OffchainSignature = Signature;
+    type OffchainPublic = <Signature as Verify>::Signer;
+    type MaxSignedIntents = ConstU32<100>;
+    type SolverBond = ConstU128<1_000_000>;
+    // Solvers are disabled, batches are cleared by the pallet
+    type BiddingPeriod = ConstU32<0>;
+    type SolverFee = ConstU128<100>;
+    // Added liquidity is locked for an epoch
+    type LiquidityHoldingPeriod = ConstU32<10>;
+    type PriceBand = PriceBand;
+    type DefaultImpactThreshold = DefaultImpactThreshold;
+    #[cfg(feature = "runtime-benchmarks")]
+    type BenchmarkHelper = AntiMevBenchmarkHelper;
+}
+
+/// Creates the assets traded in the pallet-anti-mev-amm benchmarks.
+#[cfg(feature = "runtime-benchmarks")]
+pub struct AntiMevBenchmarkHelper;
+
+#[cfg(feature = "runtime-benchmarks")]
+impl pallet_anti_mev_amm::BenchmarkHelper<u32> for AntiMevBenchmarkHelper {
+    fn asset_id(seed: u32) -> u32 {
+        u32::MAX - seed
+    }
+
+    fn create_asset(asset_id: &u32) {
+        use frame::deps::frame_support::traits::fungibles::Create;
+        let owner = <Runtime as pallet_anti_mev_amm::ConfigHelper>::pallet_account();
+        <Assets as Create<AccountId>>::create(*asset_id, owner, true, 1)
+            .expect("benchmark asset is new");
+    }
 }
 
 
@@ -223,8 +300,11 @@ type SignedExtra = (
 // `<type as trait>::associated` to create local aliases to them.
 
 type AccountId = <Runtime as frame_system::Config>::AccountId;
+type Signature = frame::runtime::types_common::Signature;
 type Balance = <Runtime as pallet_balances::Config>::Balance;
 type Nonce = <Runtime as frame_system::Config>::Nonce;
+type Hash = <Runtime as frame_system::Config>::Hash;
+type BlockNumber = BlockNumberFor<Runtime>;
 type Block = frame::runtime::types_common::BlockOf<Runtime, SignedExtra>;
 type Header = HeaderFor<Runtime>;
 
@@ -328,6 +408,20 @@ impl_runtime_apis! {
 		}
 	}
 
+	impl pallet_anti_mev_amm::runtime_api::AntiMevAmmApi<
+		Block,
+		u32,
+		AccountId,
+		Balance,
+		Balance,
+	> for Runtime {
+		fn simulate_settlement(
+			asset_id: u32,
+		) -> Result<pallet_anti_mev_amm::types::SettlementPreview<AccountId, Balance, Balance>, DispatchError> {
+			AntiMevAmm::preview_settlement(&asset_id)
+		}
+	}
+
 	impl pallet_transaction_payment_rpc_runtime_api::TransactionPaymentApi<
 		Block,
 		Balance,